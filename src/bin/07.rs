@@ -3,8 +3,11 @@ advent_of_code::solution!(7);
 use advent_of_code::template::RunType;
 
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, File};
 use std::iter::Peekable;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
 
 use anyhow::{anyhow, Context, Result};
 
@@ -17,7 +20,11 @@ enum LsEntry {
 #[derive(Debug)]
 enum Command {
     Cd(PathBuf),
-    Ls(Vec<(PathBuf, LsEntry)>),
+    Ls(Option<PathBuf>, Vec<(PathBuf, LsEntry)>),
+    Mkdir(PathBuf),
+    Rm(PathBuf),
+    Mv(PathBuf, PathBuf),
+    Touch(usize, PathBuf),
 }
 
 #[derive(Debug)]
@@ -47,12 +54,202 @@ fn calc_size(path: &Path, dirs: &BTreeMap<PathBuf, Directory>) -> usize {
     total_size
 }
 
+/// Resolves `path` against `cwd` into an absolute path with any `.` and `..`
+/// segments removed. Going above `/` just stays at `/` like a real shell.
+fn resolve(cwd: &Path, path: &Path) -> PathBuf {
+    let mut out = if path.has_root() {
+        PathBuf::from("/")
+    } else {
+        cwd.to_path_buf()
+    };
+    for component in path.components() {
+        match component {
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::Normal(name) => out.push(name),
+        }
+    }
+    out
+}
+
+fn parent_of(path: &Path) -> Result<&Path> {
+    path.parent()
+        .with_context(|| format!("{:?} has no parent directory", path))
+}
+
+struct Filesystem {
+    cwd: PathBuf,
+    dirs: BTreeMap<PathBuf, Directory>,
+}
+
+impl Filesystem {
+    fn new() -> Self {
+        let mut dirs = BTreeMap::new();
+        dirs.insert("/".into(), Directory::new());
+        Self {
+            cwd: "/".into(),
+            dirs,
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.parent()
+            .and_then(|parent| self.dirs.get(parent))
+            .map(|dir| dir.files.contains_key(path))
+            .unwrap_or(false)
+    }
+
+    fn parent_mut(&mut self, path: &Path) -> Result<&mut Directory> {
+        let parent = parent_of(path)?;
+        self.dirs
+            .get_mut(parent)
+            .with_context(|| format!("parent directory {:?} does not exist", parent))
+    }
+
+    fn add_dir(&mut self, path: PathBuf) -> Result<()> {
+        if self.is_file(&path) {
+            return Err(anyhow!("can't create directory {:?}, file exists", path));
+        }
+        self.parent_mut(&path)?.dirs.insert(path.clone());
+        self.dirs.entry(path).or_insert_with(Directory::new);
+        Ok(())
+    }
+
+    fn add_file(&mut self, path: PathBuf, size: usize) -> Result<()> {
+        if self.dirs.contains_key(&path) {
+            return Err(anyhow!("can't create file {:?}, directory exists", path));
+        }
+        self.parent_mut(&path)?.files.insert(path, size);
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<()> {
+        if path == Path::new("/") {
+            return Err(anyhow!("refusing to remove /"));
+        }
+        if self.dirs.contains_key(path) {
+            self.parent_mut(path)?.dirs.remove(path);
+            self.dirs.retain(|dir, _| !dir.starts_with(path));
+        } else if self.is_file(path) {
+            self.parent_mut(path)?.files.remove(path);
+        } else {
+            return Err(anyhow!(
+                "can't remove {:?}, no such file or directory",
+                path
+            ));
+        }
+        Ok(())
+    }
+
+    fn rename(&mut self, source: &Path, dest: &Path) -> Result<()> {
+        let dest = if self.dirs.contains_key(dest) {
+            dest.join(source.file_name().context("can't move /")?)
+        } else {
+            dest.to_path_buf()
+        };
+        if dest.starts_with(source) {
+            return Err(anyhow!("can't move {:?} into itself", source));
+        }
+        if self.dirs.contains_key(&dest) || self.is_file(&dest) {
+            return Err(anyhow!(
+                "can't move {:?} to {:?}, target exists",
+                source,
+                dest
+            ));
+        }
+
+        if self.is_file(source) {
+            let size = self.dirs[parent_of(source)?].files[source];
+            self.remove(source)?;
+            return self.add_file(dest, size);
+        }
+        if !self.dirs.contains_key(source) {
+            return Err(anyhow!(
+                "can't move {:?}, no such file or directory",
+                source
+            ));
+        }
+
+        let rebase = |path: &Path| dest.join(path.strip_prefix(source).unwrap());
+        let moved: Vec<PathBuf> = self
+            .dirs
+            .keys()
+            .filter(|dir| dir.starts_with(source))
+            .cloned()
+            .collect();
+        self.parent_mut(&dest)?;
+        self.parent_mut(source)?.dirs.remove(source);
+        for old in moved {
+            let dir = self.dirs.remove(&old).unwrap();
+            self.dirs.insert(
+                rebase(&old),
+                Directory {
+                    dirs: dir.dirs.iter().map(|p| rebase(p)).collect(),
+                    files: dir.files.iter().map(|(p, s)| (rebase(p), *s)).collect(),
+                },
+            );
+        }
+        self.parent_mut(&dest)?.dirs.insert(dest.clone());
+        Ok(())
+    }
+
+    fn apply(&mut self, command: &Command) -> Result<()> {
+        match command {
+            Command::Cd(path) => {
+                self.cwd = resolve(&self.cwd, path);
+            }
+            Command::Ls(path, entries) => {
+                let target = match path {
+                    Some(path) => resolve(&self.cwd, path),
+                    None => self.cwd.clone(),
+                };
+                if !self.dirs.contains_key(&target) {
+                    self.add_dir(target.clone())?;
+                }
+                for (name, entry) in entries {
+                    let full_path = target.join(name);
+                    match entry {
+                        LsEntry::Dir => self.add_dir(full_path)?,
+                        LsEntry::File(size) => self.add_file(full_path, *size)?,
+                    }
+                }
+            }
+            Command::Mkdir(path) => {
+                let path = resolve(&self.cwd, path);
+                if self.dirs.contains_key(&path) {
+                    return Err(anyhow!("can't create directory {:?}, it exists", path));
+                }
+                self.add_dir(path)?;
+            }
+            Command::Rm(path) => {
+                let path = resolve(&self.cwd, path);
+                self.remove(&path)?;
+            }
+            Command::Mv(source, dest) => {
+                let source = resolve(&self.cwd, source);
+                let dest = resolve(&self.cwd, dest);
+                self.rename(&source, &dest)?;
+            }
+            Command::Touch(size, path) => {
+                let path = resolve(&self.cwd, path);
+                self.add_file(path, *size)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<'a, I> TryFrom<&mut Peekable<I>> for Command
 where
     I: Iterator<Item = &'a str>,
 {
     type Error = anyhow::Error;
 
+    /// Paths run to the end of the line like the names in `ls` output, so
+    /// they can hold spaces. `mv` is the exception as it takes two, and
+    /// either can be double quoted.
     fn try_from(other: &mut Peekable<I>) -> Result<Self> {
         let cmd = other
             .next()
@@ -60,41 +257,59 @@ where
             .strip_prefix("$ ")
             .context("Got unexpected non-command line in input")?;
 
-        Ok(if cmd.starts_with("cd") {
-            let (_, target) = cmd
-                .split_once(" ")
-                .context("expected to find dir after cd")?;
+        let (name, rest) = cmd.split_once(' ').unwrap_or((cmd, ""));
+        let path = |what: &str| -> Result<PathBuf> {
+            if rest.is_empty() {
+                return Err(anyhow!("expected to find {} after {}", what, name));
+            }
+            Ok(rest.into())
+        };
 
-            Self::Cd(target.into())
-        } else if cmd.starts_with("ls") {
-            let mut entries = Vec::new();
-            loop {
-                match other.peek() {
-                    None => break,
-                    Some(line) => {
-                        if line.starts_with("$") {
-                            break;
+        Ok(match name {
+            "cd" => Self::Cd(path("dir")?),
+            "ls" => {
+                let mut entries = Vec::new();
+                loop {
+                    match other.peek() {
+                        None => break,
+                        Some(line) => {
+                            if line.starts_with("$") {
+                                break;
+                            }
                         }
                     }
+                    let line = other.next().unwrap();
+                    let (first, second) = line
+                        .split_once(" ")
+                        .context("Expected to find `<x> <name>` in ls entry")?;
+
+                    if first == "dir" {
+                        entries.push((second.into(), LsEntry::Dir));
+                    } else {
+                        entries.push((
+                            second.into(),
+                            LsEntry::File(first.parse().context("Invalid file size found")?),
+                        ));
+                    }
                 }
-                let line = other.next().unwrap();
-                let (first, second) = line
-                    .trim()
-                    .split_once(" ")
-                    .context("Expected to find `<x> <name>` in ls entry")?;
-
-                if first == "dir" {
-                    entries.push((second.into(), LsEntry::Dir));
-                } else {
-                    entries.push((
-                        second.into(),
-                        LsEntry::File(first.parse().context("Invalid file size found")?),
-                    ));
-                }
+                Self::Ls((!rest.is_empty()).then(|| rest.into()), entries)
             }
-            Self::Ls(entries)
-        } else {
-            return Err(anyhow!("Invalid command {:?} found", cmd));
+            "mkdir" => Self::Mkdir(path("dir")?),
+            "rm" => Self::Rm(path("path")?),
+            "mv" => match tokenize(rest)?.as_slice() {
+                [source, dest] => Self::Mv(source.into(), dest.into()),
+                args => return Err(anyhow!("mv takes two paths but got {:?}", args)),
+            },
+            "touch" => {
+                let (size, file) = rest
+                    .split_once(' ')
+                    .context("expected to find `<size> <path>` after touch")?;
+                Self::Touch(
+                    size.parse().context("Invalid file size found")?,
+                    file.into(),
+                )
+            }
+            _ => return Err(anyhow!("Invalid command {:?} found", cmd)),
         })
     }
 }
@@ -106,69 +321,585 @@ fn build_dirs(input: &str) -> Result<BTreeMap<PathBuf, Directory>> {
         commands.push(Command::try_from(&mut lines).context("failed to parse command")?);
     }
 
-    let mut cwd: PathBuf = "/".into();
-    let mut dirs: BTreeMap<PathBuf, Directory> = BTreeMap::new();
+    let mut fs = Filesystem::new();
     for command in &commands {
-        match command {
-            Command::Cd(path) => {
-                if path == Path::new("/") {
-                    cwd = "/".into();
-                } else if path == Path::new("..") {
-                    cwd.pop();
-                } else {
-                    cwd = cwd.join(path);
-                }
+        fs.apply(command)
+            .with_context(|| format!("failed to apply {:?}", command))?;
+    }
+    Ok(fs.dirs)
+}
+
+/// Writes the tree out under `root`, with every file created sparse so even
+/// the real input only takes up a handful of blocks.
+fn materialize(dirs: &BTreeMap<PathBuf, Directory>, root: &Path) -> Result<()> {
+    let on_disk = |path: &Path| root.join(path.strip_prefix("/").unwrap_or(path));
+    for (path, dir) in dirs {
+        let target = on_disk(path);
+        fs::create_dir_all(&target).with_context(|| format!("failed to create {:?}", target))?;
+        for (file, size) in &dir.files {
+            let target = on_disk(file);
+            File::create(&target)
+                .and_then(|handle| handle.set_len(*size as u64))
+                .with_context(|| format!("failed to create {:?}", target))?;
+        }
+    }
+    Ok(())
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<(String, fs::Metadata)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {:?}", dir))? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow!("non utf-8 file name {:?}", name))?;
+        entries.push((name, entry.metadata()?));
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+fn write_transcript(dir: &Path, out: &mut String) -> Result<()> {
+    let entries = sorted_entries(dir)?;
+    if let Some((name, _)) = entries.iter().find(|(name, _)| name.contains('\n')) {
+        return Err(anyhow!("{:?} in {:?} can't go in a transcript", name, dir));
+    }
+    out.push_str("$ ls\n");
+    for (name, metadata) in &entries {
+        if metadata.is_dir() {
+            out.push_str(&format!("dir {}\n", name));
+        } else if metadata.is_file() {
+            out.push_str(&format!("{} {}\n", metadata.len(), name));
+        }
+    }
+    for (name, metadata) in &entries {
+        if metadata.is_dir() {
+            out.push_str(&format!("$ cd {}\n", name));
+            write_transcript(&dir.join(name), out)?;
+            out.push_str("$ cd ..\n");
+        }
+    }
+    Ok(())
+}
+
+/// Walks a real directory and produces the `$ cd`/`$ ls` session that would
+/// have explored it. Anything that isn't a plain file or directory is skipped.
+/// Names go in as they are since the parser reads them to the end of the
+/// line, which leaves only names with a newline in them that won't fit.
+fn transcript(root: &Path) -> Result<String> {
+    let mut out = String::from("$ cd /\n");
+    write_transcript(root, &mut out)?;
+    Ok(out)
+}
+
+/// Total length of every file under `path`. This matches `du -sb` minus the
+/// space the directory entries themselves take up.
+fn apparent_size(path: &Path) -> Result<usize> {
+    let mut total = 0;
+    for (name, metadata) in sorted_entries(path)? {
+        if metadata.is_dir() {
+            total += apparent_size(&path.join(name))?;
+        } else if metadata.is_file() {
+            total += metadata.len() as usize;
+        }
+    }
+    Ok(total)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    Dir,
+    File,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    path: PathBuf,
+    kind: EntryKind,
+    size: usize,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            EntryKind::Dir => 'd',
+            EntryKind::File => 'f',
+        };
+        write!(f, "{}\t{}\t{}", kind, self.size, self.path.display())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filter {
+    Type(EntryKind),
+    /// In bytes, unlike find's 512 byte blocks: `+N` is bigger than N bytes,
+    /// `-N` is smaller than N and a bare `N` is exactly N.
+    Size(std::cmp::Ordering, usize),
+    /// Glob on the entry name supporting `*` and `?`.
+    Name(String),
+}
+
+impl Filter {
+    fn matches(&self, record: &Record) -> bool {
+        match self {
+            Filter::Type(kind) => record.kind == *kind,
+            Filter::Size(ordering, size) => record.size.cmp(size) == *ordering,
+            Filter::Name(pattern) => {
+                let name = record
+                    .path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("/");
+                glob_match(pattern.as_bytes(), name.as_bytes())
             }
-            Command::Ls(entries) => {
-                let dir = dirs.entry(cwd.clone()).or_insert_with(Directory::new);
-                for entry in entries {
-                    let full_path = cwd.join(&entry.0);
-                    match entry.1 {
-                        LsEntry::Dir => {
-                            dir.dirs.insert(full_path);
-                        }
-                        LsEntry::File(size) => {
-                            dir.files.insert(full_path, size);
-                        }
-                    }
+        }
+    }
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Queries over the tree from `build_dirs`, either
+/// `find <path> [-type d|f] [-size [+-]N] [-name GLOB]` or
+/// `top <n> <path> [filters...]` for the n largest matching entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Query {
+    Find {
+        root: PathBuf,
+        filters: Vec<Filter>,
+    },
+    Top {
+        count: usize,
+        root: PathBuf,
+        filters: Vec<Filter>,
+    },
+}
+
+fn tokenize(query: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut token = String::new();
+        if c == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err(anyhow!("unterminated quote in {:?}", query)),
                 }
             }
+        } else {
+            token.push(c);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_filters(tokens: &[String]) -> Result<Vec<Filter>> {
+    let mut filters = Vec::new();
+    let mut tokens = tokens.iter();
+    while let Some(flag) = tokens.next() {
+        let value = tokens
+            .next()
+            .with_context(|| format!("expected value after {:?}", flag))?;
+        filters.push(match flag.as_str() {
+            "-type" => Filter::Type(match value.as_str() {
+                "d" => EntryKind::Dir,
+                "f" => EntryKind::File,
+                other => return Err(anyhow!("unknown type {:?}, expected d or f", other)),
+            }),
+            "-size" => {
+                let (ordering, size) = if let Some(size) = value.strip_prefix('+') {
+                    (std::cmp::Ordering::Greater, size)
+                } else if let Some(size) = value.strip_prefix('-') {
+                    (std::cmp::Ordering::Less, size)
+                } else {
+                    (std::cmp::Ordering::Equal, value.as_str())
+                };
+                Filter::Size(ordering, size.parse().context("invalid size in -size")?)
+            }
+            "-name" => Filter::Name(value.clone()),
+            other => return Err(anyhow!("unknown filter {:?}", other)),
+        });
+    }
+    Ok(filters)
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(query: &str) -> Result<Self> {
+        let tokens = tokenize(query)?;
+        match tokens.first().map(|t| t.as_str()) {
+            Some("find") => Ok(Query::Find {
+                root: tokens.get(1).context("expected path after find")?.into(),
+                filters: parse_filters(&tokens[2..])?,
+            }),
+            Some("top") => Ok(Query::Top {
+                count: tokens
+                    .get(1)
+                    .context("expected count after top")?
+                    .parse()
+                    .context("invalid count for top")?,
+                root: tokens.get(2).context("expected path after count")?.into(),
+                filters: parse_filters(&tokens[3..])?,
+            }),
+            _ => Err(anyhow!(
+                "expected query to start with find or top: {:?}",
+                query
+            )),
+        }
+    }
+}
+
+impl Query {
+    fn run(&self, dirs: &BTreeMap<PathBuf, Directory>) -> Result<Vec<Record>> {
+        let (root, filters) = match self {
+            Query::Find { root, filters } | Query::Top { root, filters, .. } => (root, filters),
+        };
+        let root = resolve(Path::new("/"), root);
+        if !dirs.contains_key(&root) {
+            return Err(anyhow!("no such directory {:?}", root));
+        }
+
+        let mut records = Vec::new();
+        for (path, dir) in dirs.range(root.clone()..) {
+            if !path.starts_with(&root) {
+                break;
+            }
+            records.push(Record {
+                path: path.clone(),
+                kind: EntryKind::Dir,
+                size: calc_size(path, dirs),
+            });
+            for (file, size) in &dir.files {
+                records.push(Record {
+                    path: file.clone(),
+                    kind: EntryKind::File,
+                    size: *size,
+                });
+            }
+        }
+        records.retain(|record| filters.iter().all(|filter| filter.matches(record)));
+
+        if let Query::Top { count, .. } = self {
+            records.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+            records.truncate(*count);
         }
+        Ok(records)
+    }
+}
+
+/// Optional flags for moving between the model and a real directory, eg
+/// `cargo run --bin 07 -- --materialize /tmp/day7` or `--transcript ~/src`,
+/// plus `--query "top 5 / -type d"` to run a query against the input and
+/// `--plan` to print the cleanup plan part two picks from.
+struct Options {
+    materialize: Option<PathBuf>,
+    transcript: Option<PathBuf>,
+    query: Option<Query>,
+    plan: bool,
+}
+
+impl Options {
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            materialize: args.opt_value_from_str("--materialize")?,
+            transcript: args.opt_value_from_str("--transcript")?,
+            query: args.opt_value_from_str("--query")?,
+            plan: args.contains("--plan"),
+        })
     }
-    Ok(dirs)
 }
 
+aoc_solutions::day_options!(Options);
+
 pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let options = Options::get()?;
+    if let Some(root) = &options.transcript {
+        print!("{}", transcript(root)?);
+    }
+
     let dirs = build_dirs(input)?;
-    let mut total = 0;
-    for dir in dirs.keys() {
-        let size = calc_size(dir, &dirs);
-        if size <= 100000 {
-            total += size;
+    if let Some(root) = &options.materialize {
+        materialize(&dirs, root)?;
+        println!(
+            "materialized {} bytes under {:?}, model says {}",
+            apparent_size(root)?,
+            root,
+            calc_size(Path::new("/"), &dirs)
+        );
+    }
+    if let Some(query) = &options.query {
+        for record in query.run(&dirs)? {
+            println!("{}", record);
+        }
+    }
+
+    let small: Query = "find / -type d -size -100001".parse()?;
+    Ok(Some(
+        small.run(&dirs)?.iter().map(|record| record.size).sum(),
+    ))
+}
+
+const DISK_SIZE: usize = 70000000;
+const UPDATE_SIZE: usize = 30000000;
+
+#[derive(Debug, Clone, Copy)]
+struct CleanupConfig {
+    capacity: usize,
+    required: usize,
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DISK_SIZE,
+            required: UPDATE_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Deletion {
+    freed: usize,
+    dirs: Vec<PathBuf>,
+}
+
+impl fmt::Display for Deletion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.freed)?;
+        for (idx, dir) in self.dirs.iter().enumerate() {
+            if idx != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", dir.display())?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Debug)]
+struct CleanupPlan {
+    to_free: usize,
+    single: Option<Deletion>,
+    multi: Option<Deletion>,
+}
+
+impl fmt::Display for CleanupPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "need to free {}", self.to_free)?;
+        for (name, deletion) in [("single", &self.single), ("multi", &self.multi)] {
+            match deletion {
+                Some(deletion) => writeln!(f, "{}: {}", name, deletion)?,
+                None => writeln!(f, "{}: impossible", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn flatten<'a>(
+    path: &'a Path,
+    dirs: &'a BTreeMap<PathBuf, Directory>,
+    order: &mut Vec<(&'a Path, usize)>,
+) {
+    let idx = order.len();
+    order.push((path, 0));
+    for subpath in &dirs.get(path).unwrap().dirs {
+        flatten(subpath, dirs, order);
+    }
+    order[idx].1 = order.len();
+}
+
+/// Fixed size set of sums in `0..len` stored as a bitset.
+#[derive(Clone)]
+struct SumSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl SumSet {
+    fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    fn insert(&mut self, value: usize) {
+        self.words[value / 64] |= 1 << (value % 64);
+    }
+
+    fn union(&mut self, other: &SumSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    fn difference(&mut self, other: &SumSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= !other;
+        }
+    }
+
+    /// Every value in `self` plus `amount`, dropping anything past `len`.
+    fn shifted(&self, amount: usize) -> SumSet {
+        let mut out = SumSet::new(self.len);
+        let (word_shift, bit_shift) = (amount / 64, amount % 64);
+        for idx in (word_shift..out.words.len()).rev() {
+            let src = idx - word_shift;
+            let mut word = self.words[src] << bit_shift;
+            if bit_shift != 0 && src > 0 {
+                word |= self.words[src - 1] >> (64 - bit_shift);
+            }
+            out.words[idx] = word;
+        }
+        let tail = self.len % 64;
+        if tail != 0 {
+            if let Some(last) = out.words.last_mut() {
+                *last &= (1 << tail) - 1;
+            }
         }
+        out
+    }
+
+    /// Values in the set from `start` upwards in ascending order.
+    fn iter_from(&self, start: usize) -> impl Iterator<Item = usize> + '_ {
+        let first = start / 64;
+        self.words[first.min(self.words.len())..]
+            .iter()
+            .enumerate()
+            .flat_map(move |(offset, word)| {
+                let mut word = *word;
+                if offset == 0 {
+                    word &= u64::MAX << (start % 64);
+                }
+                std::iter::from_fn(move || {
+                    if word == 0 {
+                        return None;
+                    }
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some((first + offset) * 64 + bit)
+                })
+            })
     }
-    Ok(Some(total))
+}
+
+/// Finds both the smallest single directory and the smallest set of
+/// non-nested directories that free up enough space for the update.
+///
+/// The multi directory case is a 0/1 knapsack over the directories in
+/// pre-order where taking a directory skips over its whole subtree. Sums are
+/// capped at the single directory answer since nothing bigger can win, and
+/// for each sum we remember the directory that reached it at the earliest
+/// position so the chosen set can be walked back out.
+fn plan_cleanup(dirs: &BTreeMap<PathBuf, Directory>, config: CleanupConfig) -> Result<CleanupPlan> {
+    let in_use = calc_size(Path::new("/"), dirs);
+    let free = config
+        .capacity
+        .checked_sub(in_use)
+        .with_context(|| format!("{} in use is over the disk capacity", in_use))?;
+    let to_free = config.required.saturating_sub(free);
+
+    let mut order = Vec::new();
+    flatten(Path::new("/"), dirs, &mut order);
+    let sizes: Vec<usize> = order.iter().map(|(dir, _)| calc_size(dir, dirs)).collect();
+
+    let single = order
+        .iter()
+        .zip(&sizes)
+        .filter(|(_, size)| **size >= to_free)
+        .min_by_key(|((dir, _), size)| (**size, *dir))
+        .map(|((dir, _), size)| Deletion {
+            freed: *size,
+            dirs: vec![dir.to_path_buf()],
+        });
+    let Some(upper) = single.as_ref().map(|deletion| deletion.freed) else {
+        return Ok(CleanupPlan {
+            to_free,
+            single,
+            multi: None,
+        });
+    };
+
+    // current holds every sum reachable when deciding on directory idx and
+    // pending holds sums that only become reachable once we are past the
+    // subtree of the directory that produced them.
+    let mut current = SumSet::new(upper + 1);
+    current.insert(0);
+    let mut pending: BTreeMap<usize, SumSet> = BTreeMap::new();
+    let mut producer: Vec<Option<usize>> = vec![None; upper + 1];
+    for (idx, (_, skip)) in order.iter().enumerate() {
+        if let Some(arrived) = pending.remove(&idx) {
+            current.union(&arrived);
+        }
+
+        let taken = current.shifted(sizes[idx]);
+        let mut new = taken.clone();
+        new.difference(&current);
+        if let Some(landing) = pending.get(skip) {
+            new.difference(landing);
+        }
+        for freed in new.iter_from(0) {
+            producer[freed] = Some(idx);
+        }
+        pending
+            .entry(*skip)
+            .or_insert_with(|| SumSet::new(upper + 1))
+            .union(&taken);
+    }
+    for (_, arrived) in pending {
+        current.union(&arrived);
+    }
+
+    let multi = current.iter_from(to_free).next().map(|freed| {
+        let mut picked = Vec::new();
+        let mut remaining = freed;
+        while let Some(idx) = producer[remaining] {
+            picked.push(order[idx].0.to_path_buf());
+            remaining -= sizes[idx];
+        }
+        picked.reverse();
+        Deletion {
+            freed,
+            dirs: picked,
+        }
+    });
+
+    Ok(CleanupPlan {
+        to_free,
+        single,
+        multi,
+    })
 }
 
 pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
     let dirs = build_dirs(input)?;
-    let available = 70000000;
-    let in_use = calc_size(Path::new("/"), &dirs);
-    let free = available - in_use;
-    let update_size = 30000000;
-    let min_to_free = update_size - free;
-
-    let mut dir_sizes = Vec::new();
-    for dir in dirs.keys() {
-        let size = calc_size(dir, &dirs);
-        if size < min_to_free {
-            continue;
-        }
-        dir_sizes.push((size, dir.clone()));
+    let plan = plan_cleanup(&dirs, CleanupConfig::default())?;
+    if Options::get()?.plan {
+        print!("{}", plan);
     }
-    dir_sizes.sort();
 
-    Ok(dir_sizes.into_iter().next().map(|(s, _)| s))
+    Ok(plan.single.map(|deletion| deletion.freed))
 }
 
 #[cfg(test)]
@@ -194,4 +925,162 @@ mod tests_day_7 {
         assert_eq!(result, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_extended_commands() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 3);
+        assert!(!input.is_empty(), "example 3 empty!");
+        assert_eq!(part_one(input, RunType::Example)?, Some(95437));
+        assert_eq!(part_two(input, RunType::Example)?, Some(24933642));
+
+        // Names can have spaces in them, and listing a directory nobody has
+        // made yet still hangs it off its parent
+        let input = "$ cd /
+$ ls
+dir My Documents
+10 notes.txt
+$ cd My Documents
+$ ls
+200 a b.txt
+$ mkdir Old Stuff
+$ touch 30 Old Stuff/c d
+$ mv \"Old Stuff\" \"/Older Stuff\"
+$ ls /New Stuff
+4 e
+$ rm /My Documents/a b.txt";
+        let dirs = build_dirs(input)?;
+        assert_eq!(
+            dirs.keys().collect::<Vec<_>>(),
+            vec![
+                Path::new("/"),
+                Path::new("/My Documents"),
+                Path::new("/New Stuff"),
+                Path::new("/Older Stuff")
+            ]
+        );
+        assert_eq!(calc_size(Path::new("/"), &dirs), 44);
+        assert_eq!(calc_size(Path::new("/Older Stuff"), &dirs), 30);
+        assert!(build_dirs("$ cd").is_err());
+        assert!(build_dirs("$ mv /a").is_err());
+        assert!(build_dirs("$ ls /a/b").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_planner() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 4);
+        assert!(!input.is_empty(), "example 4 empty!");
+        let dirs = build_dirs(input)?;
+        let plan = plan_cleanup(
+            &dirs,
+            CleanupConfig {
+                capacity: 1000,
+                required: 870,
+            },
+        )?;
+        assert_eq!(plan.to_free, 100);
+        assert_eq!(
+            plan.single,
+            Some(Deletion {
+                freed: 120,
+                dirs: vec!["/z".into()],
+            })
+        );
+        assert_eq!(
+            plan.multi,
+            Some(Deletion {
+                freed: 110,
+                dirs: vec!["/x".into(), "/y".into()],
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_round_trip() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        round_trip(input, "example")?;
+        // Including names that start or end with a space
+        let spaces = "$ cd /
+$ ls
+dir My Documents
+5 to do.txt
+$ cd My Documents
+$ ls
+dir  old\x20
+7 a b
+$ cd  old\x20
+$ ls
+9 c";
+        round_trip(spaces, "spaces")
+    }
+
+    /// Writes the tree out and checks that both it and the transcript of
+    /// what got written match the tree.
+    fn round_trip(input: &str, name: &str) -> anyhow::Result<()> {
+        let dirs = build_dirs(input)?;
+        let root = std::env::temp_dir().join(format!("aoc-day-7-{}-{}", name, std::process::id()));
+        materialize(&dirs, &root)?;
+
+        let result = (|| {
+            for dir in dirs.keys() {
+                let on_disk = root.join(dir.strip_prefix("/")?);
+                assert_eq!(apparent_size(&on_disk)?, calc_size(dir, &dirs), "{:?}", dir);
+            }
+
+            let replayed = build_dirs(&transcript(&root)?)?;
+            assert_eq!(
+                replayed.keys().collect::<Vec<_>>(),
+                dirs.keys().collect::<Vec<_>>()
+            );
+            for dir in dirs.keys() {
+                assert_eq!(
+                    calc_size(dir, &replayed),
+                    calc_size(dir, &dirs),
+                    "{:?}",
+                    dir
+                );
+            }
+            Ok(())
+        })();
+        fs::remove_dir_all(&root)?;
+        result
+    }
+
+    #[test]
+    fn test_queries() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let dirs = build_dirs(input)?;
+
+        let query: Query = r#"find / -type f -size +10000 -name "*.*""#.parse()?;
+        let paths: Vec<_> = query.run(&dirs)?.into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("/b.txt"),
+                "/c.dat".into(),
+                "/a/h.lst".into(),
+                "/d/d.ext".into(),
+                "/d/d.log".into(),
+            ]
+        );
+
+        let query: Query = "top 2 / -type d".parse()?;
+        assert_eq!(
+            query.run(&dirs)?,
+            vec![
+                Record {
+                    path: "/".into(),
+                    kind: EntryKind::Dir,
+                    size: 48381165,
+                },
+                Record {
+                    path: "/d".into(),
+                    kind: EntryKind::Dir,
+                    size: 24933642,
+                },
+            ]
+        );
+        Ok(())
+    }
 }
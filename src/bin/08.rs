@@ -2,76 +2,591 @@ advent_of_code::solution!(8);
 
 use advent_of_code::template::RunType;
 
-use anyhow::{Context, Result};
-use aoc_lib::grid::{CountingMap, Direction, Map};
+use anyhow::{anyhow, Context, Result};
+use aoc_lib::grid::Map;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+/// Tree heights stored row by row so columns are a fixed stride apart.
+struct Forest {
+    heights: Vec<u32>,
+    rows: usize,
+    cols: usize,
+}
+
+fn parse(input: &str) -> Result<Forest> {
     let map: Map<u32> = Map::parse(input, |c| c.to_digit(10).context("failed to parse digit"))
         .context("failed to parse input")?;
 
-    let mut visible: CountingMap = (&map).into();
-    for (mut current, direction) in map.get_edges() {
-        visible.mark(&current);
-        let mut max = map.get(&current);
-        loop {
-            current = match map.go_direction(&current, &direction) {
-                Some(next) => next,
-                None => break,
-            };
-            let height = map.get(&current);
-            if height > max {
-                visible.mark(&current);
-            }
+    let mut heights = Vec::new();
+    let mut rows = 0;
+    for row in map.iter() {
+        heights.extend(row.map(|(_, height)| *height));
+        rows += 1;
+    }
+    let cols = heights.len().checked_div(rows).unwrap_or(0);
+    Ok(Forest {
+        heights,
+        rows,
+        cols,
+    })
+}
 
-            max = std::cmp::max(max, height);
-            if *max == 9 {
-                break;
+/// Per tree results of looking along every row and column in both
+/// directions, indexed the same way as `Forest::heights`.
+struct Sightlines {
+    visible: Vec<bool>,
+    /// Wider than the puzzle needs, as four distances in a large forest
+    /// multiply past `u32::MAX`. With enough rays even this isn't wide
+    /// enough, so scores stop at `u64::MAX` rather than wrapping.
+    scenic: Vec<u64>,
+}
+
+/// Looks back along one line of trees keeping a stack of the trees that could
+/// still block the view of whatever comes next. Anything shorter than the
+/// current tree is popped since the current tree hides it from everything
+/// further along, so each tree is pushed and popped once.
+///
+/// Whatever is left on top is the tree blocking the view. If nothing is left
+/// the current tree is taller than everything before it, so it's visible from
+/// that edge.
+#[derive(Debug, Clone, Default)]
+struct LineOfSight {
+    stack: Vec<(usize, u32)>,
+}
+
+impl LineOfSight {
+    /// Adds the tree at `cell`, which is `idx` trees in from the edge.
+    fn step(&mut self, idx: usize, cell: usize, height: u32, out: &mut Sightlines) {
+        while self.stack.last().is_some_and(|(_, top)| *top < height) {
+            self.stack.pop();
+        }
+        let distance = match self.stack.last() {
+            Some((top, _)) => idx - top,
+            None => {
+                out.visible[cell] = true;
+                idx
             }
+        };
+        out.scenic[cell] = out.scenic[cell].saturating_mul(distance as u64);
+        self.stack.push((idx, height));
+    }
+}
+
+/// Which ways to look from each tree, as (row, col) steps. Each step is
+/// repeated until the edge so `(1, 2)` looks along a line of knight moves.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum Rays {
+    #[default]
+    Cardinal,
+    Diagonal,
+    All,
+    Custom(Vec<(isize, isize)>),
+}
+
+impl Rays {
+    fn steps(&self) -> Vec<(isize, isize)> {
+        const CARDINAL: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+        const DIAGONAL: [(isize, isize); 4] = [(-1, 1), (1, 1), (1, -1), (-1, -1)];
+        match self {
+            Rays::Cardinal => CARDINAL.to_vec(),
+            Rays::Diagonal => DIAGONAL.to_vec(),
+            Rays::All => CARDINAL.iter().chain(&DIAGONAL).copied().collect(),
+            Rays::Custom(steps) => steps.clone(),
         }
     }
-    Ok(Some(visible.unique()))
 }
 
-pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<u32>, anyhow::Error> {
-    let map: Map<u32> = Map::parse(input, |c| c.to_digit(10).context("failed to parse digit"))
-        .context("failed to parse input")?;
+impl FromStr for Rays {
+    type Err = anyhow::Error;
 
-    let mut best = None;
-    for row in map.iter() {
-        for (loc, current_height) in row {
-            let mut senic = 1;
-            for direction in Direction::all() {
-                let mut dist = 0;
-                let mut current = loc.clone();
-                while let Some(next) = map.go_direction(&current, direction) {
-                    let next_height = map.get(&next);
-                    dist += 1;
-                    if next_height >= current_height {
-                        break;
+    /// Either a preset name or steps like `"1,2 2,1 -1,2"`.
+    fn from_str(spec: &str) -> Result<Self> {
+        Ok(match spec {
+            "cardinal" => Rays::Cardinal,
+            "diagonal" => Rays::Diagonal,
+            "all" => Rays::All,
+            custom => {
+                let mut steps = Vec::new();
+                for step in custom.split_whitespace() {
+                    let (row, col) = step
+                        .split_once(',')
+                        .with_context(|| format!("expected `row,col` ray but got {:?}", step))?;
+                    let step = (
+                        row.parse().context("invalid ray row")?,
+                        col.parse().context("invalid ray col")?,
+                    );
+                    if step == (0, 0) {
+                        return Err(anyhow!("ray can't be 0,0"));
                     }
-                    current = next;
+                    steps.push(step);
+                }
+                if steps.is_empty() {
+                    return Err(anyhow!("expected at least one ray"));
                 }
-                senic *= dist
+                Rays::Custom(steps)
             }
-            match best {
-                Some(current) => {
-                    if senic > current {
-                        best = Some(senic);
-                    }
+        })
+    }
+}
+
+impl Forest {
+    fn contains(&self, row: isize, col: isize) -> bool {
+        (0..self.rows as isize).contains(&row) && (0..self.cols as isize).contains(&col)
+    }
+}
+
+/// Every tree belongs to exactly one line per ray, which starts at the tree
+/// whose next step leaves the forest. Walking each line backwards from there
+/// means the stack holds exactly the trees that tree can look out over.
+fn sightlines(forest: &Forest, rays: &Rays) -> Sightlines {
+    let mut out = Sightlines {
+        visible: vec![false; forest.heights.len()],
+        scenic: vec![1; forest.heights.len()],
+    };
+
+    let mut line = LineOfSight::default();
+    for (d_row, d_col) in rays.steps() {
+        for start_row in 0..forest.rows as isize {
+            for start_col in 0..forest.cols as isize {
+                if forest.contains(start_row + d_row, start_col + d_col) {
+                    continue;
                 }
-                None => {
-                    best = Some(senic);
+                line.stack.clear();
+                let (mut row, mut col) = (start_row, start_col);
+                let mut idx = 0;
+                while forest.contains(row, col) {
+                    let cell = row as usize * forest.cols + col as usize;
+                    line.step(idx, cell, forest.heights[cell], &mut out);
+                    row -= d_row;
+                    col -= d_col;
+                    idx += 1;
                 }
             }
         }
     }
+    out
+}
+
+impl Sightlines {
+    /// Cell with the highest scenic score, picking the first on ties.
+    fn best(&self) -> Option<usize> {
+        let max = self.scenic.iter().max()?;
+        self.scenic.iter().position(|score| score == max)
+    }
+
+    /// Scenic scores scaled to 0-255 on a log scale, otherwise the few great
+    /// spots wash out everything else.
+    fn scenic_levels(&self) -> Vec<u8> {
+        let max = (*self.scenic.iter().max().unwrap_or(&0) as f64).ln_1p();
+        self.scenic
+            .iter()
+            .map(|score| {
+                if max > 0.0 {
+                    ((*score as f64).ln_1p() / max * 255.0).round() as u8
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+}
+
+/// Black through red and yellow to white.
+fn heat(level: u8) -> [u8; 3] {
+    let t = level as f64 / 255.0 * 3.0;
+    let channel = |offset: f64| ((t - offset).clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(0.0), channel(1.0), channel(2.0)]
+}
+
+const BEST_MARKER: [u8; 3] = [0, 255, 255];
+
+/// Binary PPM of the scenic scores with the best spot in cyan.
+fn write_scenic_ppm<W: Write>(out: &mut W, forest: &Forest, sightlines: &Sightlines) -> Result<()> {
+    write!(out, "P6\n{} {}\n255\n", forest.cols, forest.rows)?;
+    let best = sightlines.best();
+    for (cell, level) in sightlines.scenic_levels().into_iter().enumerate() {
+        if Some(cell) == best {
+            out.write_all(&BEST_MARKER)?;
+        } else {
+            out.write_all(&heat(level))?;
+        }
+    }
+    Ok(())
+}
+
+/// Binary PGM of the visibility mask, white for visible trees and black for
+/// hidden ones. The best spot is mid grey since PGM has no colour.
+fn write_visible_pgm<W: Write>(
+    out: &mut W,
+    forest: &Forest,
+    sightlines: &Sightlines,
+) -> Result<()> {
+    write!(out, "P5\n{} {}\n255\n", forest.cols, forest.rows)?;
+    let best = sightlines.best();
+    for (cell, visible) in sightlines.visible.iter().enumerate() {
+        let level = match (Some(cell) == best, visible) {
+            (true, _) => 128,
+            (false, true) => 255,
+            (false, false) => 0,
+        };
+        out.write_all(&[level])?;
+    }
+    Ok(())
+}
+
+/// Scenic scores as ANSI background colours, with trees that can't be seen
+/// from outside dotted and the best spot marked `<>`.
+fn render_heatmap(forest: &Forest, sightlines: &Sightlines) -> String {
+    let best = sightlines.best();
+    let levels = sightlines.scenic_levels();
+    let mut out = String::new();
+    for row in 0..forest.rows {
+        for col in 0..forest.cols {
+            let cell = row * forest.cols + col;
+            let ([r, g, b], text) = if Some(cell) == best {
+                (BEST_MARKER, "<>")
+            } else if sightlines.visible[cell] {
+                (heat(levels[cell]), "  ")
+            } else {
+                (heat(levels[cell]), " ·")
+            };
+            // Dark text on the bright end of the scale and light text otherwise
+            let fg = if levels[cell] > 127 { 30 } else { 97 };
+            out.push_str(&format!("\x1b[{};48;2;{};{};{}m{}", fg, r, g, b, text));
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Objective {
+    /// Sum of the scenic scores of every site.
+    #[default]
+    Scenic,
+    /// Number of distinct trees that can be seen from at least one site.
+    Coverage,
+}
+
+impl FromStr for Objective {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "scenic" => Ok(Objective::Scenic),
+            "coverage" => Ok(Objective::Coverage),
+            other => Err(anyhow!(
+                "unknown objective {:?}, expected scenic or coverage",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Placement {
+    /// (row, col) of each treehouse in the order they were picked.
+    sites: Vec<(usize, usize)>,
+    value: u64,
+}
+
+/// Everything needed to score a set of treehouse sites, worked out once up
+/// front so both solvers only deal in cell indexes.
+struct Planner<'a> {
+    forest: &'a Forest,
+    objective: Objective,
+    /// Sites must be at least this far apart by manhattan distance.
+    spacing: usize,
+    scenic: Vec<u64>,
+    /// The trees each site can see, up to and including whatever blocks it.
+    views: Vec<Vec<usize>>,
+}
+
+impl<'a> Planner<'a> {
+    fn new(forest: &'a Forest, rays: &Rays, objective: Objective, spacing: usize) -> Self {
+        let steps = rays.steps();
+        let views = match objective {
+            Objective::Scenic => Vec::new(),
+            Objective::Coverage => (0..forest.heights.len())
+                .map(|cell| {
+                    let (row, col) = ((cell / forest.cols) as isize, (cell % forest.cols) as isize);
+                    let mut seen = Vec::new();
+                    for (d_row, d_col) in &steps {
+                        let (mut r, mut c) = (row + d_row, col + d_col);
+                        while forest.contains(r, c) {
+                            let other = r as usize * forest.cols + c as usize;
+                            seen.push(other);
+                            if forest.heights[other] >= forest.heights[cell] {
+                                break;
+                            }
+                            r += d_row;
+                            c += d_col;
+                        }
+                    }
+                    seen
+                })
+                .collect(),
+        };
+        Self {
+            forest,
+            objective,
+            spacing,
+            scenic: sightlines(forest, rays).scenic,
+            views,
+        }
+    }
+
+    fn location(&self, cell: usize) -> (usize, usize) {
+        (cell / self.forest.cols, cell % self.forest.cols)
+    }
+
+    fn allowed(&self, picked: &[usize], cell: usize) -> bool {
+        let (row, col) = self.location(cell);
+        picked.iter().all(|other| {
+            let (other_row, other_col) = self.location(*other);
+            row.abs_diff(other_row) + col.abs_diff(other_col) >= self.spacing.max(1)
+        })
+    }
+
+    /// How much adding `cell` would add on top of what `covered` already has.
+    fn gain(&self, covered: &[bool], cell: usize) -> u64 {
+        match self.objective {
+            Objective::Scenic => self.scenic[cell],
+            Objective::Coverage => self.views[cell]
+                .iter()
+                .filter(|tree| !covered[**tree])
+                .count() as u64,
+        }
+    }
+
+    fn placement(&self, picked: &[usize], value: u64) -> Placement {
+        Placement {
+            sites: picked.iter().map(|cell| self.location(*cell)).collect(),
+            value,
+        }
+    }
+
+    /// Takes whichever allowed site adds the most each round, stopping early
+    /// if spacing means nothing else fits. This is
+    /// optimal for the scenic objective without spacing but only a
+    /// reasonable first guess otherwise.
+    fn greedy(&self, count: usize) -> Placement {
+        let mut covered = vec![false; self.forest.heights.len()];
+        let mut picked = Vec::new();
+        let mut value: u64 = 0;
+        while picked.len() < count {
+            let best = (0..self.forest.heights.len())
+                .filter(|cell| self.allowed(&picked, *cell))
+                .map(|cell| (self.gain(&covered, cell), std::cmp::Reverse(cell)))
+                .max();
+            let Some((gain, std::cmp::Reverse(cell))) = best else {
+                break;
+            };
+            picked.push(cell);
+            value = value.saturating_add(gain);
+            if self.objective == Objective::Coverage {
+                self.views[cell]
+                    .iter()
+                    .for_each(|tree| covered[*tree] = true);
+            }
+        }
+        self.placement(&picked, value)
+    }
+
+    /// Branch and bound over sites ordered by what they're worth on their
+    /// own. A site is never worth more added to others than alone, so the
+    /// best remaining singles bound what the rest of a branch can add.
+    /// Meant for small grids, it starts from the greedy answer but is
+    /// exponential in the worst case.
+    fn exact(&self, count: usize) -> Placement {
+        let empty = vec![false; self.forest.heights.len()];
+        let mut order: Vec<(u64, usize)> = (0..self.forest.heights.len())
+            .map(|cell| (self.gain(&empty, cell), cell))
+            .collect();
+        order.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let greedy = self.greedy(count);
+        let mut search = Search {
+            planner: self,
+            order,
+            count,
+            best: greedy.value,
+            best_picked: greedy
+                .sites
+                .iter()
+                .map(|(row, col)| row * self.forest.cols + col)
+                .collect(),
+            picked: Vec::new(),
+            covered: vec![0; self.forest.heights.len()],
+        };
+        search.run(0, 0);
+        self.placement(&search.best_picked, search.best)
+    }
+}
 
-    Ok(best)
+struct Search<'a, 'b> {
+    planner: &'b Planner<'a>,
+    order: Vec<(u64, usize)>,
+    count: usize,
+    best: u64,
+    best_picked: Vec<usize>,
+    picked: Vec<usize>,
+    /// How many picked sites see each tree, so sites can be taken back out.
+    covered: Vec<u32>,
+}
+
+impl Search<'_, '_> {
+    fn run(&mut self, from: usize, value: u64) {
+        // Spacing can make it impossible to fit every site in, so any
+        // smaller set that beats the best so far counts too.
+        if value > self.best {
+            self.best = value;
+            self.best_picked = self.picked.clone();
+        }
+        if self.picked.len() == self.count {
+            return;
+        }
+
+        let remaining = self.count - self.picked.len();
+        for idx in from..self.order.len() {
+            // order is sorted so this is the most any remaining branch can add
+            let bound: u64 = self.order[idx..]
+                .iter()
+                .take(remaining)
+                .map(|(alone, _)| *alone)
+                .fold(0, u64::saturating_add);
+            if value.saturating_add(bound) <= self.best {
+                return;
+            }
+
+            let cell = self.order[idx].1;
+            if !self.planner.allowed(&self.picked, cell) {
+                continue;
+            }
+            let gain = match self.planner.objective {
+                Objective::Scenic => self.planner.scenic[cell],
+                Objective::Coverage => {
+                    let views = &self.planner.views[cell];
+                    let gain = views
+                        .iter()
+                        .filter(|tree| self.covered[**tree] == 0)
+                        .count();
+                    views.iter().for_each(|tree| self.covered[*tree] += 1);
+                    gain as u64
+                }
+            };
+            self.picked.push(cell);
+            self.run(idx + 1, value.saturating_add(gain));
+            self.picked.pop();
+            if self.planner.objective == Objective::Coverage {
+                self.planner.views[cell]
+                    .iter()
+                    .for_each(|tree| self.covered[*tree] -= 1);
+            }
+        }
+    }
+}
+
+/// Optional flags for looking at the forest rather than just the answers, eg
+/// `cargo run --bin 08 -- --heatmap` or `--export /tmp/forest` to write
+/// `/tmp/forest-scenic.ppm` and `/tmp/forest-visible.pgm`. `--rays all`
+/// changes which ways the trees look, see `Rays`.
+///
+/// `--treehouses 3 [--spacing 4] [--objective coverage] [--exact]` plans where
+/// to put several treehouses, see `Planner`.
+struct Options {
+    heatmap: bool,
+    export: Option<PathBuf>,
+    rays: Rays,
+    treehouses: Option<usize>,
+    spacing: usize,
+    objective: Objective,
+    exact: bool,
+}
+
+impl Options {
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            heatmap: args.contains("--heatmap"),
+            export: args.opt_value_from_str("--export")?,
+            rays: args.opt_value_from_str("--rays")?.unwrap_or_default(),
+            treehouses: args.opt_value_from_str("--treehouses")?,
+            spacing: args.opt_value_from_str("--spacing")?.unwrap_or(1),
+            objective: args.opt_value_from_str("--objective")?.unwrap_or_default(),
+            exact: args.contains("--exact"),
+        })
+    }
+}
+
+aoc_solutions::day_options!(Options);
+
+fn export(prefix: &Path, forest: &Forest, sightlines: &Sightlines) -> Result<()> {
+    let with_suffix = |suffix: &str| {
+        let mut path = prefix.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+
+    let path = with_suffix("-scenic.ppm");
+    let mut out = BufWriter::new(
+        File::create(&path).with_context(|| format!("failed to create {:?}", path))?,
+    );
+    write_scenic_ppm(&mut out, forest, sightlines)?;
+    out.flush()?;
+
+    let path = with_suffix("-visible.pgm");
+    let mut out = BufWriter::new(
+        File::create(&path).with_context(|| format!("failed to create {:?}", path))?,
+    );
+    write_visible_pgm(&mut out, forest, sightlines)?;
+    out.flush()?;
+    Ok(())
+}
+
+pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let forest = parse(input)?;
+    let options = Options::get()?;
+    let sightlines = sightlines(&forest, &options.rays);
+
+    Ok(Some(sightlines.visible.iter().filter(|v| **v).count()))
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<u64>, anyhow::Error> {
+    let forest = parse(input)?;
+    let options = Options::get()?;
+    let sightlines = sightlines(&forest, &options.rays);
+
+    if options.heatmap {
+        print!("{}", render_heatmap(&forest, &sightlines));
+    }
+    if let Some(prefix) = &options.export {
+        export(prefix, &forest, &sightlines)?;
+    }
+    if let Some(count) = options.treehouses {
+        let planner = Planner::new(&forest, &options.rays, options.objective, options.spacing);
+        let placement = if options.exact {
+            planner.exact(count)
+        } else {
+            planner.greedy(count)
+        };
+        println!(
+            "{:?} {}: {:?}",
+            options.objective, placement.value, placement.sites
+        );
+    }
+
+    Ok(sightlines.scenic.iter().max().copied())
 }
 
 #[cfg(test)]
 mod tests_day_8 {
     use super::*;
+    use aoc_lib::grid::{CountingMap, Direction};
+    use aoc_solutions::random::Lcg;
+    use itertools::Itertools;
 
     #[test]
     fn test_part_one() -> anyhow::Result<()> {
@@ -92,4 +607,265 @@ mod tests_day_8 {
         assert_eq!(result, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_images() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        let forest = parse(input)?;
+        let sightlines = sightlines(&forest, &Rays::Cardinal);
+        let best = 3 * forest.cols + 2;
+        assert_eq!(sightlines.best(), Some(best));
+
+        let mut ppm = Vec::new();
+        write_scenic_ppm(&mut ppm, &forest, &sightlines)?;
+        let header = b"P6\n5 5\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 25 * 3);
+        let pixel = header.len() + best * 3;
+        assert_eq!(ppm[pixel..pixel + 3], BEST_MARKER);
+
+        let mut pgm = Vec::new();
+        write_visible_pgm(&mut pgm, &forest, &sightlines)?;
+        let header = b"P5\n5 5\n255\n";
+        assert_eq!(&pgm[..header.len()], header);
+        let pixels = &pgm[header.len()..];
+        assert_eq!(pixels[best], 128);
+        assert_eq!(pixels.iter().filter(|p| **p != 0).count(), 21);
+        Ok(())
+    }
+
+    /// The original walk from every edge, kept to check the stack version.
+    fn naive_visible(map: &Map<u32>) -> usize {
+        let mut visible: CountingMap = map.into();
+        for (mut current, direction) in map.get_edges() {
+            visible.mark(&current);
+            let mut max = map.get(&current);
+            while let Some(next) = map.go_direction(&current, &direction) {
+                current = next;
+                let height = map.get(&current);
+                if height > max {
+                    visible.mark(&current);
+                }
+                max = std::cmp::max(max, height);
+            }
+        }
+        visible.unique()
+    }
+
+    /// The original walk outwards from every tree.
+    fn naive_scenic(map: &Map<u32>) -> Option<u64> {
+        let mut best = None;
+        for row in map.iter() {
+            for (loc, current_height) in row {
+                let mut senic = 1;
+                for direction in Direction::all() {
+                    let mut dist = 0;
+                    let mut current = loc.clone();
+                    while let Some(next) = map.go_direction(&current, direction) {
+                        dist += 1;
+                        if map.get(&next) >= current_height {
+                            break;
+                        }
+                        current = next;
+                    }
+                    senic *= dist
+                }
+                best = std::cmp::max(best, Some(senic));
+            }
+        }
+        best
+    }
+
+    fn random_forest(rng: &mut Lcg, rows: usize, cols: usize) -> String {
+        let mut out = String::with_capacity((cols + 1) * rows);
+        for _ in 0..rows {
+            for _ in 0..cols {
+                out.push(char::from_digit(rng.below(10) as u32, 10).unwrap());
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    #[test]
+    fn test_matches_naive() -> anyhow::Result<()> {
+        let mut rng = Lcg::new(8);
+        for size in 1..40 {
+            let (rows, cols) = (size, (size * 7) % 23 + 1);
+            let input = random_forest(&mut rng, rows, cols);
+            let map: Map<u32> = Map::parse(&input, |c| c.to_digit(10).context("bad digit"))?;
+            assert_eq!(
+                part_one(&input, RunType::Example)?,
+                Some(naive_visible(&map))
+            );
+            assert_eq!(part_two(&input, RunType::Example)?, naive_scenic(&map));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_rays_match_naive() -> anyhow::Result<()> {
+        let knight: Rays = "1,2 2,1 -1,2 -2,1 1,-2 2,-1 -1,-2 -2,-1".parse()?;
+        assert_eq!(knight.steps().len(), 8);
+        assert!("0,0".parse::<Rays>().is_err());
+
+        let mut rng = Lcg::new(32);
+        for size in 1..20 {
+            let input = random_forest(&mut rng, size, (size * 5) % 17 + 1);
+            let forest = parse(&input)?;
+            for rays in [Rays::Diagonal, Rays::All, knight.clone()] {
+                let fast = sightlines(&forest, &rays);
+                for cell in 0..forest.heights.len() {
+                    let height = forest.heights[cell];
+                    let mut visible = false;
+                    let mut scenic = 1;
+                    for step in rays.steps() {
+                        let mut dist = 0;
+                        let (mut row, mut col) = (cell / forest.cols, cell % forest.cols);
+                        let mut blocked = false;
+                        loop {
+                            let (next_row, next_col) =
+                                (row as isize + step.0, col as isize + step.1);
+                            if !forest.contains(next_row, next_col) {
+                                break;
+                            }
+                            (row, col) = (next_row as usize, next_col as usize);
+                            dist += 1;
+                            if forest.heights[row * forest.cols + col] >= height {
+                                blocked = true;
+                                break;
+                            }
+                        }
+                        visible |= !blocked;
+                        scenic *= dist;
+                    }
+                    assert_eq!(fast.visible[cell], visible, "{:?} {}", rays, cell);
+                    assert_eq!(fast.scenic[cell], scenic, "{:?} {}", rays, cell);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_long_rays() -> anyhow::Result<()> {
+        // One tall tree in the middle of a flat 99x99 forest looks 49 or 24
+        // trees along each of these, and 49^8 * 24^8 doesn't fit in a u64
+        let size = 99;
+        let mut input = String::new();
+        for row in 0..size {
+            for col in 0..size {
+                input.push(if (row, col) == (49, 49) { '9' } else { '0' });
+            }
+            input.push('\n');
+        }
+        let forest = parse(&input)?;
+        let rays: Rays = "0,1 1,0 0,-1 -1,0 1,1 1,-1 -1,1 -1,-1 \
+                          0,2 2,0 0,-2 -2,0 2,2 2,-2 -2,2 -2,-2"
+            .parse()?;
+        let scenic = sightlines(&forest, &rays).scenic;
+        assert_eq!(scenic[49 * size + 49], u64::MAX);
+        assert_eq!(scenic[50 * size + 50], 1);
+        assert_eq!(scenic[0], 0);
+
+        let planner = Planner::new(&forest, &rays, Objective::Scenic, 1);
+        assert_eq!(planner.greedy(3).value, u64::MAX);
+        assert_eq!(planner.exact(3).value, u64::MAX);
+        Ok(())
+    }
+
+    #[test]
+    fn test_treehouses() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        let forest = parse(input)?;
+        let planner = Planner::new(&forest, &Rays::Cardinal, Objective::Scenic, 1);
+        let greedy = planner.greedy(2);
+        assert_eq!(greedy.sites, vec![(3, 2), (2, 1)]);
+        assert_eq!(greedy.value, 14);
+        assert_eq!(planner.exact(2), greedy);
+
+        // Check the exact solver against trying every pair and triple
+        let mut rng = Lcg::new(33);
+        for size in 2..6 {
+            let input = random_forest(&mut rng, size, size + 1);
+            let forest = parse(&input)?;
+            let cells = forest.heights.len();
+            for (objective, spacing) in [
+                (Objective::Coverage, 1),
+                (Objective::Coverage, 3),
+                (Objective::Scenic, 2),
+            ] {
+                let planner = Planner::new(&forest, &Rays::Cardinal, objective, spacing);
+                for count in 2..=3 {
+                    let mut best = 0;
+                    for picked in (1..=count).flat_map(|size| (0..cells).combinations(size)) {
+                        if (1..picked.len())
+                            .any(|idx| !planner.allowed(&picked[..idx], picked[idx]))
+                        {
+                            continue;
+                        }
+                        let mut covered = vec![false; cells];
+                        let mut value = 0;
+                        for cell in picked {
+                            value += planner.gain(&covered, cell);
+                            if objective == Objective::Coverage {
+                                planner.views[cell]
+                                    .iter()
+                                    .for_each(|tree| covered[*tree] = true);
+                            }
+                        }
+                        best = std::cmp::max(best, value);
+                    }
+                    let exact = planner.exact(count);
+                    assert_eq!(exact.value, best, "{:?} {} {}", objective, spacing, count);
+                    assert!(planner.greedy(count).value <= exact.value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_options() -> anyhow::Result<()> {
+        // What the parts see under test, whatever libtest was started with
+        let options = Options::get()?;
+        assert!(!options.exact && options.treehouses.is_none());
+
+        let args = ["--treehouses", "3", "--exact", "--objective", "coverage"];
+        let args = pico_args::Arguments::from_vec(args.iter().map(|a| a.into()).collect());
+        let options = Options::parse(args)?;
+        assert_eq!(options.treehouses, Some(3));
+        assert!(options.exact);
+        assert_eq!(options.objective, Objective::Coverage);
+        assert_eq!(options.spacing, 1);
+        Ok(())
+    }
+
+    /// Run with `cargo test --release --bin 08 -- --ignored --nocapture`.
+    ///
+    /// This checks the stacks agree with the walks at scale more than it
+    /// shows them winning: both take about the same time. A walk only goes
+    /// past trees shorter than every tree it has passed, and with heights of
+    /// 0-9 that's at most ten walks over any one tree per direction, so the
+    /// walks are linear too for any forest the puzzle can give.
+    #[test]
+    #[ignore]
+    fn bench_large_forest() -> anyhow::Result<()> {
+        let input = random_forest(&mut Lcg::new(2022), 2000, 2000);
+        let map: Map<u32> = Map::parse(&input, |c| c.to_digit(10).context("bad digit"))?;
+        let forest = parse(&input)?;
+
+        let start = std::time::Instant::now();
+        let sightlines = sightlines(&forest, &Rays::Cardinal);
+        let visible = sightlines.visible.iter().filter(|v| **v).count();
+        let fast = (Some(visible), sightlines.scenic.iter().max().copied());
+        println!("stacks: {:?}", start.elapsed());
+
+        let start = std::time::Instant::now();
+        let naive = (Some(naive_visible(&map)), naive_scenic(&map));
+        println!("naive: {:?}", start.elapsed());
+
+        assert_eq!(fast, naive);
+        Ok(())
+    }
 }
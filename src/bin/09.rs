@@ -5,92 +5,627 @@ use advent_of_code::template::RunType;
 use anyhow::{anyhow, Context, Result};
 use aoc_lib::grid::{Direction, UnboundLocation};
 use aoc_lib::parse::preamble::*;
-use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
 
-fn parse(input: &str) -> Result<Vec<(Direction, usize)>> {
+/// Which way the head goes each step: one of `U`, `R`, `D` or `L`, or a
+/// diagonal made of one vertical and one horizontal direction such as `UR`.
+#[derive(Debug, Clone)]
+struct Heading(Vec<Direction>);
+
+impl Heading {
+    fn apply(&self, loc: &UnboundLocation) -> UnboundLocation {
+        self.0
+            .iter()
+            .fold(loc.clone(), |loc, direction| loc.go_direction(direction, 1))
+    }
+}
+
+impl FromStr for Heading {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut vertical = None;
+        let mut horizontal = None;
+        for c in s.chars() {
+            let (axis, direction) = match c {
+                'U' => (&mut vertical, Direction::North),
+                'D' => (&mut vertical, Direction::South),
+                'R' => (&mut horizontal, Direction::East),
+                'L' => (&mut horizontal, Direction::West),
+                other => {
+                    return Err(anyhow!("Got unexpected direction char {:?}", other));
+                }
+            };
+            if axis.replace(direction).is_some() {
+                return Err(anyhow!("{:?} goes along the same axis twice", s));
+            }
+        }
+        let directions: Vec<Direction> = vertical.into_iter().chain(horizontal).collect();
+        if directions.is_empty() {
+            return Err(anyhow!("Missing direction"));
+        }
+        Ok(Heading(directions))
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<(Heading, usize)>> {
     parse_input(
         LineSplitter,
-        ParseTuple2(
-            SingleChar(ParseFn(|c| {
-                Ok(match c {
-                    'U' => Direction::North,
-                    'R' => Direction::East,
-                    'D' => Direction::South,
-                    'L' => Direction::West,
-                    other => {
-                        return Err(anyhow!("Got unexpected direction char {:?}", other));
-                    }
-                })
-            })),
-            ParseFromStr,
-            " ",
-        ),
+        ParseTuple2(ParseFromStr, ParseFromStr, " "),
         input,
     )
     .context("Failed to parse input")
 }
 
-fn calc_tail(head: &UnboundLocation, tail: &UnboundLocation) -> UnboundLocation {
-    let delta_row = tail.0 - head.0;
-    let delta_col = tail.1 - head.1;
-
-    match (delta_row.abs(), delta_col.abs()) {
-        (0, 0) | (1, 0) | (0, 1) | (1, 1) => {
-            return tail.clone();
-        }
-        _ => {}
-    };
-    if head.0 == tail.0 || delta_col.abs() > delta_row.abs() {
-        UnboundLocation(head.0, head.1 + (delta_col) / delta_col.abs())
-    } else if head.1 == tail.1 || delta_row.abs() > delta_col.abs() {
-        UnboundLocation(head.0 + (delta_row) / delta_row.abs(), head.1)
-    } else {
-        UnboundLocation(
-            head.0 + (delta_row) / delta_row.abs(),
-            head.1 + (delta_col) / delta_col.abs(),
-        )
+/// How a knot keeps up with the one in front of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FollowRule {
+    /// Stays put while within `slack` cells on both axes, otherwise steps
+    /// straight or diagonally towards the knot in front. The puzzle's rule is
+    /// a slack of 1.
+    Slack(usize),
+    /// Stays put while within `slack` steps counting both axes, otherwise
+    /// steps along whichever axis it is furthest behind on, never diagonally.
+    Manhattan(usize),
+}
+
+impl Default for FollowRule {
+    fn default() -> Self {
+        FollowRule::Slack(1)
     }
 }
 
-fn run(input: &str, rope_len: usize) -> Result<Option<usize>> {
-    let data = parse(input)?;
-    let mut locations: BTreeSet<UnboundLocation> = BTreeSet::new();
+impl FromStr for FollowRule {
+    type Err = anyhow::Error;
+
+    /// `slack`, `manhattan`, or either followed by `:k` for a slack of `k`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, slack) = match s.split_once(':') {
+            Some((name, slack)) => (
+                name,
+                slack
+                    .parse()
+                    .with_context(|| format!("Bad slack {:?}", slack))?,
+            ),
+            None => (s, 1),
+        };
+        match name {
+            "slack" => Ok(FollowRule::Slack(slack)),
+            "manhattan" => Ok(FollowRule::Manhattan(slack)),
+            other => Err(anyhow!("Unknown follow rule {:?}", other)),
+        }
+    }
+}
+
+impl FollowRule {
+    /// Drags `knot` towards `prev` until they are touching again, returning
+    /// whether it moved. Nothing moves more than one cell along each axis in
+    /// a step so one diagonal step always catches up, but a Manhattan
+    /// follower can end up two behind and has to take two steps, so the
+    /// cells it passes through on the way are pushed onto `passed`.
+    fn follow(
+        &self,
+        prev: &UnboundLocation,
+        knot: &mut UnboundLocation,
+        passed: &mut Vec<UnboundLocation>,
+    ) -> bool {
+        match *self {
+            FollowRule::Slack(slack) => {
+                let delta_row = prev.0 - knot.0;
+                let delta_col = prev.1 - knot.1;
+                if delta_row.unsigned_abs() as usize <= slack
+                    && delta_col.unsigned_abs() as usize <= slack
+                {
+                    return false;
+                }
+                knot.0 += delta_row.signum();
+                knot.1 += delta_col.signum();
+                true
+            }
+            FollowRule::Manhattan(slack) => {
+                let mut moved = false;
+                loop {
+                    let delta_row = prev.0 - knot.0;
+                    let delta_col = prev.1 - knot.1;
+                    let rows = delta_row.unsigned_abs() as usize;
+                    let cols = delta_col.unsigned_abs() as usize;
+                    if rows + cols <= slack {
+                        return moved;
+                    }
+                    if moved {
+                        passed.push(knot.clone());
+                    }
+                    if rows >= cols {
+                        knot.0 += delta_row.signum();
+                    } else {
+                        knot.1 += delta_col.signum();
+                    }
+                    moved = true;
+                }
+            }
+        }
+    }
+}
+
+fn extend_bounds(min: &mut UnboundLocation, max: &mut UnboundLocation, loc: &UnboundLocation) {
+    min.0 = std::cmp::min(min.0, loc.0);
+    min.1 = std::cmp::min(min.1, loc.1);
+    max.0 = std::cmp::max(max.0, loc.0);
+    max.1 = std::cmp::max(max.1, loc.1);
+}
+
+/// Every knot only ever moves towards the one in front of it, so the whole
+/// rope stays inside the box the head moves around in.
+fn head_bounds(moves: &[(Heading, usize)]) -> (UnboundLocation, UnboundLocation) {
     let mut head = UnboundLocation(0, 0);
-    let mut rope = Vec::new();
-    for _ in 0..(rope_len - 1) {
-        rope.push(UnboundLocation(0, 0));
+    let mut min = head.clone();
+    let mut max = head.clone();
+    for (heading, distance) in moves {
+        for _ in 0..*distance {
+            head = heading.apply(&head);
+            extend_bounds(&mut min, &mut max, &head);
+        }
+    }
+    (min, max)
+}
+
+/// Anything up to this many cells gets a bitmap, 32MiB per knot at most.
+const MAX_DENSE_CELLS: u64 = 1 << 28;
+
+enum Cells {
+    Dense(Vec<u64>),
+    Sparse(HashSet<u64>),
+}
+
+/// Cells a knot has been on, indexed row by row from the top left of the
+/// head's bounding box. Small boxes get a flat bitmap and anything else falls
+/// back to hashing the same indexes.
+struct Visited {
+    min: UnboundLocation,
+    width: u64,
+    cells: Cells,
+    count: usize,
+}
+
+impl Visited {
+    fn new(min: &UnboundLocation, max: &UnboundLocation) -> Self {
+        let width = (max.1 - min.1) as u64 + 1;
+        let height = (max.0 - min.0) as u64 + 1;
+        let cells = match width.checked_mul(height) {
+            Some(area) if area <= MAX_DENSE_CELLS => {
+                Cells::Dense(vec![0; area.div_ceil(64) as usize])
+            }
+            _ => Cells::Sparse(HashSet::new()),
+        };
+        Self {
+            min: min.clone(),
+            width,
+            cells,
+            count: 0,
+        }
+    }
+
+    fn insert(&mut self, loc: &UnboundLocation) {
+        let idx = (loc.0 - self.min.0) as u64 * self.width + (loc.1 - self.min.1) as u64;
+        let new = match &mut self.cells {
+            Cells::Dense(words) => {
+                let word = &mut words[(idx / 64) as usize];
+                let bit = 1 << (idx % 64);
+                let new = *word & bit == 0;
+                *word |= bit;
+                new
+            }
+            Cells::Sparse(cells) => cells.insert(idx),
+        };
+        if new {
+            self.count += 1;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+}
+
+/// What one knot got up to over the whole simulation.
+struct KnotStats {
+    visited: Visited,
+    /// Bounding box of everywhere the knot has been
+    min: UnboundLocation,
+    max: UnboundLocation,
+    /// Number of cells the knot moved, diagonal moves count as one
+    distance: usize,
+    /// The first step (counting from 1) that moved this knot
+    first_move: Option<usize>,
+}
+
+impl KnotStats {
+    fn new(start: &UnboundLocation, bounds: &(UnboundLocation, UnboundLocation)) -> Self {
+        let mut visited = Visited::new(&bounds.0, &bounds.1);
+        visited.insert(start);
+        Self {
+            visited,
+            min: start.clone(),
+            max: start.clone(),
+            distance: 0,
+            first_move: None,
+        }
     }
 
-    locations.insert(rope.last().unwrap().clone());
-    for (direction, distance) in &data {
+    /// Called every step that the knot moves with the cells it passed
+    /// through and its new location.
+    fn record(&mut self, step: usize, passed: &[UnboundLocation], new: &UnboundLocation) {
+        self.distance += passed.len() + 1;
+        self.first_move.get_or_insert(step);
+        for loc in passed.iter().chain(std::iter::once(new)) {
+            extend_bounds(&mut self.min, &mut self.max, loc);
+            self.visited.insert(loc);
+        }
+    }
+}
+
+impl std::fmt::Display for KnotStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "visited {}, moved {} ",
+            self.visited.len(),
+            self.distance
+        )?;
+        match self.first_move {
+            Some(step) => write!(f, "(first at step {})", step)?,
+            None => write!(f, "(never)")?,
+        }
+        write!(
+            f,
+            ", bounds ({}, {})..=({}, {})",
+            self.min.0, self.min.1, self.max.0, self.max.1
+        )
+    }
+}
+
+/// Knots updated in place with the head first.
+struct Rope {
+    knots: Vec<UnboundLocation>,
+    /// The cells each knot passed through on its way to where it is now in
+    /// the last step, which is only ever any for Manhattan followers
+    passed: Vec<Vec<UnboundLocation>>,
+    rule: FollowRule,
+}
+
+impl Rope {
+    fn new(len: usize, rule: FollowRule) -> Self {
+        Self {
+            knots: vec![UnboundLocation(0, 0); len],
+            passed: vec![Vec::new(); len],
+            rule,
+        }
+    }
+
+    /// Moves the head one step and drags the rest of the rope along behind
+    /// it. Once a knot stays put nothing behind it can move either, so this
+    /// stops there and returns how many knots from the head moved.
+    fn step(&mut self, heading: &Heading) -> usize {
+        self.knots[0] = heading.apply(&self.knots[0]);
+        for idx in 1..self.knots.len() {
+            let (done, rest) = self.knots.split_at_mut(idx);
+            let passed = &mut self.passed[idx];
+            passed.clear();
+            if !self.rule.follow(&done[idx - 1], &mut rest[0], passed) {
+                return idx;
+            }
+        }
+        self.knots.len()
+    }
+}
+
+/// Runs the moves on a rope of `rope_len` knots, calling `on_step` with the
+/// step number, the whole rope and how many knots moved. The knots that moved
+/// always start from the head.
+fn simulate<F: FnMut(usize, &Rope, usize)>(
+    moves: &[(Heading, usize)],
+    rope_len: usize,
+    rule: FollowRule,
+    mut on_step: F,
+) -> Result<()> {
+    if rope_len == 0 {
+        return Err(anyhow!("rope needs at least one knot"));
+    }
+    let mut rope = Rope::new(rope_len, rule);
+    let mut step = 0;
+    for (heading, distance) in moves {
         for _ in 0..*distance {
-            head = head.go_direction(direction, 1);
-            let mut prev = head.clone();
-            let mut new_tail = Vec::with_capacity(rope.len());
-            for knot in rope {
-                let new_knot = calc_tail(&prev, &knot);
-                new_tail.push(new_knot.clone());
-                prev = new_knot;
+            step += 1;
+            let moved = rope.step(heading);
+            on_step(step, &rope, moved);
+        }
+    }
+    Ok(())
+}
+
+/// Stats for every knot in a rope of `rope_len` knots with the head first.
+fn run(input: &str, rope_len: usize, rule: FollowRule) -> Result<Vec<KnotStats>> {
+    let data = parse(input)?;
+    let bounds = head_bounds(&data);
+    let start = UnboundLocation(0, 0);
+    let mut stats: Vec<KnotStats> = (0..rope_len)
+        .map(|_| KnotStats::new(&start, &bounds))
+        .collect();
+
+    simulate(&data, rope_len, rule, |step, rope, moved| {
+        let knots = rope.knots[..moved].iter().zip(&rope.passed);
+        for ((knot, passed), stats) in knots.zip(&mut stats) {
+            stats.record(step, passed, knot);
+        }
+    })?;
+    Ok(stats)
+}
+
+fn tail_visited(input: &str, rope_len: usize, rule: FollowRule) -> Result<Option<usize>> {
+    let data = parse(input)?;
+    let (min, max) = head_bounds(&data);
+    let mut visited = Visited::new(&min, &max);
+    visited.insert(&UnboundLocation(0, 0));
+
+    simulate(&data, rope_len, rule, |_, rope, moved| {
+        if moved == rope_len {
+            for loc in &rope.passed[rope_len - 1] {
+                visited.insert(loc);
             }
+            visited.insert(&rope.knots[rope_len - 1]);
+        }
+    })?;
+    Ok(Some(visited.len()))
+}
 
-            rope = new_tail;
-            locations.insert(rope.last().unwrap().clone());
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Empty,
+    Trail,
+    Start,
+    /// Index into the rope, 0 being the head
+    Knot(usize),
+}
+
+/// Draws the rope over the box the head moves around in, keeping track of
+/// where the tail has been as it goes.
+struct Animation {
+    min: UnboundLocation,
+    width: usize,
+    height: usize,
+    rope_len: usize,
+    trail: Vec<bool>,
+}
+
+/// Frames are for looking at, anything bigger than this is a mistake.
+const MAX_FRAME_CELLS: usize = 1 << 22;
+
+impl Animation {
+    fn new(bounds: &(UnboundLocation, UnboundLocation), rope_len: usize) -> Result<Self> {
+        let (min, max) = bounds;
+        let width = (max.1 - min.1) as usize + 1;
+        let height = (max.0 - min.0) as usize + 1;
+        if width.saturating_mul(height) > MAX_FRAME_CELLS {
+            return Err(anyhow!("{}x{} is too big to animate", width, height));
+        }
+        let mut animation = Self {
+            min: min.clone(),
+            width,
+            height,
+            rope_len,
+            trail: vec![false; width * height],
+        };
+        let start = animation.index(&UnboundLocation(0, 0));
+        animation.trail[start] = true;
+        Ok(animation)
+    }
+
+    fn index(&self, loc: &UnboundLocation) -> usize {
+        (loc.0 - self.min.0) as usize * self.width + (loc.1 - self.min.1) as usize
+    }
+
+    /// Marks where the tail has been this step, which has to happen every
+    /// step whether or not a frame gets drawn.
+    fn track(&mut self, rope: &Rope) {
+        let (Some(tail), Some(passed)) = (rope.knots.last(), rope.passed.last()) else {
+            return;
+        };
+        for loc in passed.iter().chain(std::iter::once(tail)) {
+            let idx = self.index(loc);
+            self.trail[idx] = true;
+        }
+    }
+
+    /// What each cell shows, row by row from the top left. Knots nearer the
+    /// head are drawn on top.
+    fn frame(&self, knots: &[UnboundLocation]) -> Vec<Cell> {
+        let mut cells: Vec<Cell> = self
+            .trail
+            .iter()
+            .map(|seen| if *seen { Cell::Trail } else { Cell::Empty })
+            .collect();
+        cells[self.index(&UnboundLocation(0, 0))] = Cell::Start;
+        for (idx, knot) in knots.iter().enumerate().rev() {
+            let cell = self.index(knot);
+            cells[cell] = Cell::Knot(idx);
         }
+        cells
     }
-    Ok(Some(locations.len()))
+
+    fn label(&self, cell: Cell) -> char {
+        match cell {
+            Cell::Empty => '.',
+            Cell::Trail => '#',
+            Cell::Start => 's',
+            Cell::Knot(0) => 'H',
+            Cell::Knot(1) if self.rope_len == 2 => 'T',
+            Cell::Knot(idx @ 1..=9) => char::from_digit(idx as u32, 10).unwrap(),
+            Cell::Knot(idx @ 10..=35) => (b'a' + (idx - 10) as u8) as char,
+            Cell::Knot(_) => '+',
+        }
+    }
+
+    fn to_ascii(&self, cells: &[Cell]) -> String {
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for row in cells.chunks(self.width) {
+            out.extend(row.iter().map(|cell| self.label(*cell)));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn colour(&self, cell: Cell) -> [u8; 3] {
+        match cell {
+            Cell::Empty => [0, 0, 0],
+            Cell::Trail => [90, 90, 90],
+            Cell::Start => [40, 80, 200],
+            Cell::Knot(0) => [255, 0, 0],
+            // Orange just behind the head fading to yellow at the tail
+            Cell::Knot(idx) => [255, (120 + idx * 135 / self.rope_len.max(2)) as u8, 0],
+        }
+    }
+
+    /// Binary PPM with each cell drawn as a `scale` pixel square.
+    fn write_ppm<W: Write>(&self, out: &mut W, cells: &[Cell], scale: usize) -> Result<()> {
+        write!(
+            out,
+            "P6\n{} {}\n255\n",
+            self.width * scale,
+            self.height * scale
+        )?;
+        for row in cells.chunks(self.width) {
+            let mut line = Vec::with_capacity(self.width * scale * 3);
+            for cell in row {
+                let colour = self.colour(*cell);
+                for _ in 0..scale {
+                    line.extend_from_slice(&colour);
+                }
+            }
+            for _ in 0..scale {
+                out.write_all(&line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders a frame at the start and then every `every` steps, passing each to
+/// `on_frame` with the step it was taken at.
+fn animate<F: FnMut(&Animation, usize, &[Cell]) -> Result<()>>(
+    input: &str,
+    rope_len: usize,
+    rule: FollowRule,
+    every: usize,
+    mut on_frame: F,
+) -> Result<()> {
+    let data = parse(input)?;
+    let mut animation = Animation::new(&head_bounds(&data), rope_len)?;
+    let start = vec![UnboundLocation(0, 0); rope_len];
+    on_frame(&animation, 0, &animation.frame(&start))?;
+
+    let mut result = Ok(());
+    simulate(&data, rope_len, rule, |step, rope, _| {
+        animation.track(rope);
+        let due = step % every.max(1);
+        if result.is_ok() && due == 0 {
+            result = on_frame(&animation, step, &animation.frame(&rope.knots));
+        }
+    })?;
+    result
+}
+
+/// `cargo run --bin 09 -- --knots 20` prints the stats for every knot in a
+/// rope of that length on top of the usual answers.
+///
+/// `--animate N` prints the rope every N steps, with `--delay MS` to play it
+/// back in place, and `--frames /tmp/rope-` writes the same frames out as
+/// numbered PPM images `--scale` pixels per cell. Both use `--knots` or 10.
+///
+/// `--follow manhattan` or `--follow slack:2` swaps the rule the knots follow
+/// each other by, for both parts and everything above.
+struct Options {
+    follow: FollowRule,
+    knots: Option<usize>,
+    animate: Option<usize>,
+    delay: Option<u64>,
+    frames: Option<String>,
+    scale: usize,
+}
+
+impl Options {
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            follow: args.opt_value_from_str("--follow")?.unwrap_or_default(),
+            knots: args.opt_value_from_str("--knots")?,
+            animate: args.opt_value_from_str("--animate")?,
+            delay: args.opt_value_from_str("--delay")?,
+            frames: args.opt_value_from_str("--frames")?,
+            scale: args.opt_value_from_str("--scale")?.unwrap_or(4),
+        })
+    }
+}
+
+aoc_solutions::day_options!(Options);
+
+fn show_animation(input: &str, options: &Options) -> Result<()> {
+    let rope_len = options.knots.unwrap_or(10);
+    let every = options.animate.unwrap_or(1);
+    animate(
+        input,
+        rope_len,
+        options.follow,
+        every,
+        |animation, step, cells| {
+            if options.animate.is_some() {
+                match options.delay {
+                    Some(delay) => {
+                        print!(
+                            "\x1b[2J\x1b[H== Step {} ==\n{}",
+                            step,
+                            animation.to_ascii(cells)
+                        );
+                        std::thread::sleep(std::time::Duration::from_millis(delay));
+                    }
+                    None => println!("== Step {} ==\n\n{}", step, animation.to_ascii(cells)),
+                }
+            }
+            if let Some(prefix) = &options.frames {
+                let path = format!("{}{:06}.ppm", prefix, step);
+                let file =
+                    File::create(&path).with_context(|| format!("failed to create {:?}", path))?;
+                let mut out = BufWriter::new(file);
+                animation.write_ppm(&mut out, cells, options.scale)?;
+                out.flush()?;
+            }
+            Ok(())
+        },
+    )
 }
 
 pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
-    run(input, 2)
+    tail_visited(input, 2, Options::get()?.follow)
 }
 
 pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
-    run(input, 10)
+    let options = Options::get()?;
+    if let Some(knots) = options.knots {
+        for (idx, knot) in run(input, knots, options.follow)?.iter().enumerate() {
+            println!("knot {}: {}", idx, knot);
+        }
+    }
+    if options.animate.is_some() || options.frames.is_some() {
+        show_animation(input, options)?;
+    }
+    tail_visited(input, 10, options.follow)
 }
 
 #[cfg(test)]
 mod tests_day_9 {
     use super::*;
+    use aoc_solutions::random::Lcg;
 
     #[test]
     fn test_part_one() -> anyhow::Result<()> {
@@ -121,4 +656,185 @@ mod tests_day_9 {
         assert_eq!(result, expected);
         Ok(())
     }
+
+    /// The original simulation which rebuilt the rope every step.
+    fn naive_tail_visited(moves: &[(Heading, usize)], rope_len: usize) -> usize {
+        fn calc_tail(head: &UnboundLocation, tail: &UnboundLocation) -> UnboundLocation {
+            let delta_row = tail.0 - head.0;
+            let delta_col = tail.1 - head.1;
+
+            match (delta_row.abs(), delta_col.abs()) {
+                (0, 0) | (1, 0) | (0, 1) | (1, 1) => {
+                    return tail.clone();
+                }
+                _ => {}
+            };
+            if head.0 == tail.0 || delta_col.abs() > delta_row.abs() {
+                UnboundLocation(head.0, head.1 + (delta_col) / delta_col.abs())
+            } else if head.1 == tail.1 || delta_row.abs() > delta_col.abs() {
+                UnboundLocation(head.0 + (delta_row) / delta_row.abs(), head.1)
+            } else {
+                UnboundLocation(
+                    head.0 + (delta_row) / delta_row.abs(),
+                    head.1 + (delta_col) / delta_col.abs(),
+                )
+            }
+        }
+
+        let mut locations = std::collections::BTreeSet::new();
+        let mut head = UnboundLocation(0, 0);
+        let mut rope = vec![UnboundLocation(0, 0); rope_len - 1];
+        locations.insert(rope.last().unwrap().clone());
+        for (heading, distance) in moves {
+            for _ in 0..*distance {
+                head = heading.apply(&head);
+                let mut prev = head.clone();
+                let mut new_tail = Vec::with_capacity(rope.len());
+                for knot in rope {
+                    let new_knot = calc_tail(&prev, &knot);
+                    new_tail.push(new_knot.clone());
+                    prev = new_knot;
+                }
+                rope = new_tail;
+                locations.insert(rope.last().unwrap().clone());
+            }
+        }
+        locations.len()
+    }
+
+    fn random_moves(rng: &mut Lcg, count: usize, max_distance: u64) -> String {
+        let mut out = String::new();
+        for _ in 0..count {
+            let direction = ["U", "R", "D", "L"][rng.below(4) as usize];
+            let distance = rng.below(max_distance) + 1;
+            out.push_str(&format!("{} {}\n", direction, distance));
+        }
+        out
+    }
+
+    #[test]
+    fn test_matches_naive() -> anyhow::Result<()> {
+        let mut rng = Lcg::new(9);
+        for rope_len in 2..12 {
+            let input = random_moves(&mut rng, 200, 12);
+            let moves = parse(&input)?;
+            assert_eq!(
+                tail_visited(&input, rope_len, FollowRule::default())?,
+                Some(naive_tail_visited(&moves, rope_len))
+            );
+        }
+        Ok(())
+    }
+
+    /// Run with `cargo test --release --bin 09 -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_long_rope() -> anyhow::Result<()> {
+        let input = random_moves(&mut Lcg::new(2022), 1_000_000, 20);
+        let moves = parse(&input)?;
+        let steps: usize = moves.iter().map(|(_, distance)| distance).sum();
+        assert!(steps > 10_000_000);
+
+        let start = std::time::Instant::now();
+        let fast = tail_visited(&input, 10, FollowRule::default())?;
+        println!("in place: {:?} for {} steps", start.elapsed(), steps);
+
+        let start = std::time::Instant::now();
+        let naive = naive_tail_visited(&moves, 10);
+        println!("naive: {:?}", start.elapsed());
+
+        assert_eq!(fast, Some(naive));
+        Ok(())
+    }
+
+    #[test]
+    fn test_knot_stats() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let stats = run(input, 3, FollowRule::default())?;
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].distance, 24);
+        assert_eq!(stats[0].first_move, Some(1));
+        assert_eq!(stats[1].visited.len(), 13);
+        assert_eq!(stats[1].first_move, Some(2));
+        assert_eq!(stats[2].first_move, Some(3));
+        assert!(stats[2].distance < stats[1].distance);
+        assert!(run(input, 0, FollowRule::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_animation() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let mut frames = Vec::new();
+        animate(
+            input,
+            2,
+            FollowRule::default(),
+            8,
+            |animation, step, cells| {
+                frames.push((step, animation.to_ascii(cells)));
+                Ok(())
+            },
+        )?;
+        assert_eq!(frames.len(), 4);
+        assert_eq!(
+            frames[0],
+            (0, "......\n......\n......\n......\nH.....\n".to_string())
+        );
+        assert_eq!(
+            frames[3],
+            (24, "..##..\n...##.\n.TH##.\n....#.\ns###..\n".to_string())
+        );
+
+        let mut ppm = Vec::new();
+        animate(
+            input,
+            10,
+            FollowRule::default(),
+            100,
+            |animation, _, cells| animation.write_ppm(&mut ppm, cells, 2),
+        )?;
+        assert!(ppm.starts_with(b"P6\n12 10\n255\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_rules() -> anyhow::Result<()> {
+        assert!(parse("UD 1").is_err());
+        assert!(parse("RR 1").is_err());
+        assert!(parse("X 1").is_err());
+        assert_eq!(parse("UR 2\nLD 1")?.len(), 2);
+        assert_eq!(head_bounds(&parse("UR 2\nLD 1")?).0, UnboundLocation(-2, 0));
+
+        let default = FollowRule::default();
+        assert_eq!("slack".parse::<FollowRule>()?, default);
+        assert_eq!(
+            "manhattan:2".parse::<FollowRule>()?,
+            FollowRule::Manhattan(2)
+        );
+        assert!("slack:x".parse::<FollowRule>().is_err());
+        assert!("chess".parse::<FollowRule>().is_err());
+
+        // A diagonal head pulls the tail straight along behind it
+        assert_eq!(tail_visited("R 1\nUR 4", 2, default)?, Some(5));
+        // Manhattan knots go round corners instead of cutting them
+        assert_eq!(tail_visited("R 3\nU 2", 2, default)?, Some(4));
+        assert_eq!(
+            tail_visited("R 3\nU 2", 2, FollowRule::Manhattan(1))?,
+            Some(5)
+        );
+        // and still keep up when the head moves diagonally, taking two
+        // steps at a time and going through a cell on the way
+        let stats = run("UR 5", 3, FollowRule::Manhattan(1))?;
+        assert_eq!(stats[1].distance, 9);
+        assert_eq!(stats[1].visited.len(), 10);
+        assert_eq!(stats[2].distance, 8);
+        assert_eq!(stats[2].visited.len(), 9);
+        assert_eq!(tail_visited("UR 5", 3, FollowRule::Manhattan(1))?, Some(9));
+        // More slack means a lazier tail
+        assert_eq!(tail_visited("R 5", 2, FollowRule::Slack(2))?, Some(4));
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(tail_visited(input, 2, FollowRule::Slack(2))? < Some(13));
+        Ok(())
+    }
 }
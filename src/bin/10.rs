@@ -2,32 +2,709 @@ advent_of_code::solution!(10);
 
 use advent_of_code::template::RunType;
 use anyhow::{anyhow, Context, Result};
+use std::io::{BufRead, Write};
+use std::str::FromStr;
 
-#[derive(Debug, Clone)]
-enum Command {
-    Noop,
-    Addx(i32),
+/// A small CPU like the one in the handheld device. Every instruction comes
+/// from a table giving its opcode, how many cycles it takes and the effect it
+/// has on the registers once those cycles are up, so new instructions and
+/// registers can be added without touching the loop that runs them.
+mod vm {
+    use anyhow::{anyhow, Context, Result};
+
+    /// Index of a register within its `Registers`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Register(pub usize);
+
+    /// The device's only register in the puzzle.
+    pub const X: Register = Register(0);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Operand {
+        Value(i64),
+        Register(Register),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Registers {
+        names: Vec<&'static str>,
+        values: Vec<i64>,
+    }
+
+    impl Registers {
+        pub fn get(&self, register: Register) -> i64 {
+            self.values[register.0]
+        }
+
+        pub fn set(&mut self, register: Register, value: i64) {
+            self.values[register.0] = value;
+        }
+
+        /// The value of an operand, reading it from its register if needed.
+        pub fn value(&self, operand: &Operand) -> i64 {
+            match operand {
+                Operand::Value(value) => *value,
+                Operand::Register(register) => self.get(*register),
+            }
+        }
+
+        pub fn find(&self, name: &str) -> Option<Register> {
+            self.names.iter().position(|n| *n == name).map(Register)
+        }
+    }
+
+    impl std::fmt::Display for Registers {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            for (idx, (name, value)) in self.names.iter().zip(&self.values).enumerate() {
+                if idx != 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}={}", name, value)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Applied to the registers on the last cycle of an instruction.
+    pub type Effect = fn(&mut Registers, &[Operand]);
+
+    pub struct Opcode {
+        pub name: &'static str,
+        pub cycles: u32,
+        pub arity: usize,
+        pub effect: Effect,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Instruction {
+        /// Index into the `InstructionSet` this was parsed with
+        pub opcode: usize,
+        pub args: Vec<Operand>,
+    }
+
+    pub struct InstructionSet {
+        opcodes: Vec<Opcode>,
+        registers: Registers,
+    }
+
+    impl InstructionSet {
+        /// An empty set over the given registers and their starting values.
+        pub fn new(registers: &[(&'static str, i64)]) -> Self {
+            Self {
+                opcodes: Vec::new(),
+                registers: Registers {
+                    names: registers.iter().map(|(name, _)| *name).collect(),
+                    values: registers.iter().map(|(_, value)| *value).collect(),
+                },
+            }
+        }
+
+        /// Adds an opcode, replacing any existing one with the same name.
+        pub fn with(mut self, opcode: Opcode) -> Self {
+            assert!(
+                opcode.cycles > 0,
+                "{} must take at least one cycle",
+                opcode.name
+            );
+            match self.opcodes.iter().position(|o| o.name == opcode.name) {
+                Some(idx) => self.opcodes[idx] = opcode,
+                None => self.opcodes.push(opcode),
+            }
+            self
+        }
+
+        /// The handheld as the puzzle describes it.
+        pub fn device() -> Self {
+            Self::new(&[("x", 1)])
+                .with(Opcode {
+                    name: "noop",
+                    cycles: 1,
+                    arity: 0,
+                    effect: |_, _| {},
+                })
+                .with(Opcode {
+                    name: "addx",
+                    cycles: 2,
+                    arity: 1,
+                    effect: |registers, args| {
+                        let value = registers.get(X) + registers.value(&args[0]);
+                        registers.set(X, value);
+                    },
+                })
+        }
+
+        pub fn registers(&self) -> &Registers {
+            &self.registers
+        }
+
+        pub fn opcode(&self, instruction: &Instruction) -> &Opcode {
+            &self.opcodes[instruction.opcode]
+        }
+
+        /// Applies an instruction's effect as it finishes.
+        pub fn apply(&self, registers: &mut Registers, instruction: &Instruction) {
+            (self.opcode(instruction).effect)(registers, &instruction.args);
+        }
+
+        pub fn find(&self, name: &str) -> Option<usize> {
+            self.opcodes.iter().position(|o| o.name == name)
+        }
+
+        fn parse_operand(&self, arg: &str) -> Result<Operand> {
+            if let Ok(value) = arg.parse() {
+                return Ok(Operand::Value(value));
+            }
+            self.registers
+                .find(arg)
+                .map(Operand::Register)
+                .ok_or_else(|| anyhow!("{:?} is neither a number nor a register", arg))
+        }
+
+        pub fn parse_line(&self, line: &str) -> Result<Instruction> {
+            let mut words = line.split_whitespace();
+            let name = words.next().context("expected an instruction")?;
+            let opcode = self
+                .find(name)
+                .ok_or_else(|| anyhow!("Unknown command {:?}", line))?;
+            let args = words
+                .map(|arg| self.parse_operand(arg))
+                .collect::<Result<Vec<_>>>()?;
+            let arity = self.opcodes[opcode].arity;
+            if args.len() != arity {
+                return Err(anyhow!(
+                    "{} takes {} argument(s) but got {:?}",
+                    name,
+                    arity,
+                    line
+                ));
+            }
+            Ok(Instruction { opcode, args })
+        }
+
+        pub fn parse(&self, input: &str) -> Result<Vec<Instruction>> {
+            input
+                .lines()
+                .enumerate()
+                .map(|(idx, line)| {
+                    self.parse_line(line)
+                        .with_context(|| format!("line {}", idx + 1))
+                })
+                .collect()
+        }
+
+        pub fn format(&self, instruction: &Instruction) -> String {
+            let mut out = self.opcode(instruction).name.to_string();
+            for arg in &instruction.args {
+                match arg {
+                    Operand::Value(value) => out.push_str(&format!(" {}", value)),
+                    Operand::Register(register) => {
+                        out.push(' ');
+                        out.push_str(self.registers.names[register.0]);
+                    }
+                }
+            }
+            out
+        }
+    }
+
+    /// What hooks see during a cycle, before any instruction finishing on
+    /// this cycle has taken effect.
+    pub struct Cycle<'a> {
+        /// Counting from 1
+        pub number: u32,
+        pub pc: usize,
+        pub instruction: &'a Instruction,
+        /// How many earlier cycles this instruction has already spent
+        pub elapsed: u32,
+        pub registers: &'a Registers,
+    }
+
+    /// Called during every cycle the machine runs.
+    pub type Hook<'a> = Box<dyn FnMut(&Cycle) + 'a>;
+
+    pub struct Machine<'a> {
+        set: &'a InstructionSet,
+        program: &'a [Instruction],
+        registers: Registers,
+        cycle: u32,
+        pc: usize,
+        elapsed: u32,
+        hooks: Vec<Hook<'a>>,
+    }
+
+    impl<'a> Machine<'a> {
+        pub fn new(set: &'a InstructionSet, program: &'a [Instruction]) -> Self {
+            Self {
+                set,
+                program,
+                registers: set.registers().clone(),
+                cycle: 1,
+                pc: 0,
+                elapsed: 0,
+                hooks: Vec::new(),
+            }
+        }
+
+        /// Calls `hook` during every cycle from now on.
+        pub fn hook<F: FnMut(&Cycle) + 'a>(&mut self, hook: F) {
+            self.hooks.push(Box::new(hook));
+        }
+
+        /// The cycle the next `step` will run.
+        pub fn cycle(&self) -> u32 {
+            self.cycle
+        }
+
+        /// Index of the instruction the next `step` will work on.
+        pub fn pc(&self) -> usize {
+            self.pc
+        }
+
+        /// Cycles already spent on the current instruction.
+        pub fn elapsed(&self) -> u32 {
+            self.elapsed
+        }
+
+        pub fn registers(&self) -> &Registers {
+            &self.registers
+        }
+
+        pub fn current(&self) -> Option<&'a Instruction> {
+            self.program.get(self.pc)
+        }
+
+        pub fn halted(&self) -> bool {
+            self.pc >= self.program.len()
+        }
+
+        /// What hooks will see during the next `step`, or None once the
+        /// program has finished.
+        pub fn next_cycle(&self) -> Option<Cycle<'_>> {
+            Some(Cycle {
+                number: self.cycle,
+                pc: self.pc,
+                instruction: self.current()?,
+                elapsed: self.elapsed,
+                registers: &self.registers,
+            })
+        }
+
+        /// Runs a single cycle, returning false if the program had already
+        /// finished.
+        pub fn step(&mut self) -> bool {
+            let Some(instruction) = self.current() else {
+                return false;
+            };
+            let cycle = Cycle {
+                number: self.cycle,
+                pc: self.pc,
+                instruction,
+                elapsed: self.elapsed,
+                registers: &self.registers,
+            };
+            for hook in &mut self.hooks {
+                hook(&cycle);
+            }
+
+            self.cycle += 1;
+            self.elapsed += 1;
+            let opcode = self.set.opcode(instruction);
+            if self.elapsed == opcode.cycles {
+                self.set.apply(&mut self.registers, instruction);
+                self.pc += 1;
+                self.elapsed = 0;
+            }
+            true
+        }
+
+        pub fn run(&mut self) {
+            while self.step() {}
+        }
+    }
 }
 
-fn parse(input: &str) -> Result<Vec<Command>> {
-    let mut out = Vec::new();
-    for line in input.lines() {
-        if line == "noop" {
-            out.push(Command::Noop);
-            continue;
-        } else {
-            let (cmd, x) = line
-                .split_once(" ")
-                .context("expected to find 2 part cmd if not noop")?;
-            if cmd == "addx" {
-                out.push(Command::Addx(x.parse().context("failed to parse X")?));
-            } else {
-                return Err(anyhow!("Unknown command {:?}", line));
+/// Reads the capital letters the CRT draws. Each one is a 4x6 glyph, or 5
+/// wide for the odd one like `Y`, in a cell 5 columns wide.
+mod ocr {
+    use anyhow::{anyhow, Result};
+
+    pub const WIDTH: usize = 5;
+    pub const HEIGHT: usize = 6;
+
+    #[rustfmt::skip]
+    pub const GLYPHS: &[(char, [&str; HEIGHT])] = &[
+        ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+        ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+        ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+        ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+        ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+        ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+        ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+        ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+        ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+        ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+        ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+        ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+        ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+        ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+        ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+        ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+        ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+        ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+    ];
+
+    /// Lit pixels of a glyph row by row, padded out to the full cell.
+    fn pixels(rows: &[&str]) -> Vec<bool> {
+        let mut out = Vec::with_capacity(WIDTH * HEIGHT);
+        for row in rows {
+            let mut lit: Vec<bool> = row.chars().map(|c| c == '#').collect();
+            lit.resize(WIDTH, false);
+            out.extend(lit);
+        }
+        out
+    }
+
+    pub fn glyph(letter: char) -> Option<&'static [&'static str; HEIGHT]> {
+        GLYPHS
+            .iter()
+            .find(|(c, _)| *c == letter)
+            .map(|(_, rows)| rows)
+    }
+
+    /// Decodes a screen of `#` and `.` rows into its letters.
+    pub fn decode(screen: &str) -> Result<String> {
+        let rows: Vec<&str> = screen.lines().collect();
+        if rows.len() != HEIGHT {
+            return Err(anyhow!("expected {} rows but got {}", HEIGHT, rows.len()));
+        }
+        let width = rows[0].len();
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(anyhow!("screen rows are not all the same width"));
+        }
+        let known: Vec<(char, Vec<bool>)> = GLYPHS
+            .iter()
+            .map(|(c, glyph)| (*c, pixels(glyph)))
+            .collect();
+
+        let mut out = String::new();
+        for start in (0..width).step_by(WIDTH) {
+            let end = std::cmp::min(start + WIDTH, width);
+            let cell: Vec<&str> = rows.iter().map(|row| &row[start..end]).collect();
+            let lit = pixels(&cell);
+            if lit.iter().all(|pixel| !pixel) {
+                continue;
+            }
+            match known.iter().find(|(_, glyph)| *glyph == lit) {
+                Some((c, _)) => out.push(*c),
+                None => {
+                    return Err(anyhow!(
+                        "unknown glyph in columns {}..{}:\n{}",
+                        start,
+                        end,
+                        cell.join("\n")
+                    ))
+                }
             }
         }
+        Ok(out)
     }
+}
+
+/// An extended assembly syntax for the device, assembled down to the plain
+/// instructions it understands, and listings going the other way. Programs
+/// never branch, so the cycle each instruction starts on and the registers
+/// at that point are all known without running anything.
+///
+/// On top of the plain instructions the syntax has:
+///
+/// * comments from `;` to the end of the line
+/// * `name:` labels, which can also go in front of an instruction
+/// * `macro name a b` ... `endm` definitions, called as `name 1 2` with `$a`
+///   and `$b` replaced in the body, which can't hold labels as every call
+///   would define them again
+/// * `wait n` for n `noop`s
+/// * `set v` for an `addx` taking X to v
+/// * `until c` for `noop`s until the next instruction starts on cycle c
+mod asm {
+    use super::vm::{Instruction, InstructionSet, Operand, Registers, X};
+    use anyhow::{anyhow, Context, Result};
+    use std::collections::HashMap;
+
+    /// Macros can call other macros but not forever.
+    const MAX_DEPTH: usize = 16;
 
-    Ok(out)
+    pub struct Assembled {
+        pub program: Vec<Instruction>,
+        /// Each label and the index of the instruction following it
+        pub labels: Vec<(String, usize)>,
+    }
+
+    struct Macro {
+        params: Vec<String>,
+        body: Vec<(usize, String)>,
+    }
+
+    struct Assembler<'a> {
+        set: &'a InstructionSet,
+        macros: HashMap<String, Macro>,
+        out: Assembled,
+        cycle: u32,
+        registers: Registers,
+    }
+
+    fn is_identifier(s: &str) -> bool {
+        let mut chars = s.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Replaces each `$name` in a macro body line with the argument given
+    /// for that parameter, taking the whole name so `$ab` is never read as
+    /// `$a` followed by `b`.
+    fn substitute(line: &str, params: &[String], args: &[&str]) -> Result<String> {
+        let mut out = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find('$') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let end = after
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(after.len());
+            let name = &after[..end];
+            let idx = params
+                .iter()
+                .position(|param| param == name)
+                .ok_or_else(|| anyhow!("Unknown parameter ${}", name))?;
+            out.push_str(args[idx]);
+            rest = &after[end..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    impl<'a> Assembler<'a> {
+        fn push(&mut self, instruction: Instruction) {
+            self.cycle += self.set.opcode(&instruction).cycles;
+            self.set.apply(&mut self.registers, &instruction);
+            self.out.program.push(instruction);
+        }
+
+        /// Parses a plain instruction, reading any registers it uses now as
+        /// the device only takes numbers.
+        fn push_line(&mut self, line: &str) -> Result<()> {
+            let mut instruction = self.set.parse_line(line)?;
+            for arg in &mut instruction.args {
+                *arg = Operand::Value(self.registers.value(arg));
+            }
+            self.push(instruction);
+            Ok(())
+        }
+
+        fn line(&mut self, line: &str, depth: usize) -> Result<()> {
+            let mut line = line.trim();
+            if let Some((label, rest)) = line.split_once(':') {
+                let label = label.trim();
+                if !is_identifier(label) {
+                    return Err(anyhow!("Bad label {:?}", label));
+                }
+                if self.out.labels.iter().any(|(l, _)| l == label) {
+                    return Err(anyhow!("Label {:?} defined twice", label));
+                }
+                self.out
+                    .labels
+                    .push((label.to_string(), self.out.program.len()));
+                line = rest.trim();
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => Ok(()),
+                ["wait", count] => {
+                    let count: usize = count.parse().context("Bad wait")?;
+                    for _ in 0..count {
+                        self.push_line("noop")?;
+                    }
+                    Ok(())
+                }
+                ["set", value] => {
+                    let value: i64 = value.parse().context("Bad set")?;
+                    let delta = value - self.registers.get(X);
+                    if delta != 0 {
+                        self.push_line(&format!("addx {}", delta))?;
+                    }
+                    Ok(())
+                }
+                ["until", cycle] => {
+                    let cycle: u32 = cycle.parse().context("Bad until")?;
+                    if cycle < self.cycle {
+                        return Err(anyhow!("Already at cycle {}", self.cycle));
+                    }
+                    while self.cycle < cycle {
+                        self.push_line("noop")?;
+                    }
+                    Ok(())
+                }
+                [name, args @ ..] if self.macros.contains_key(*name) => {
+                    if depth >= MAX_DEPTH {
+                        return Err(anyhow!("Macros nested more than {} deep", MAX_DEPTH));
+                    }
+                    let called = &self.macros[*name];
+                    if called.params.len() != args.len() {
+                        return Err(anyhow!(
+                            "{} takes {} argument(s) but got {}",
+                            name,
+                            called.params.len(),
+                            args.len()
+                        ));
+                    }
+                    let body = called
+                        .body
+                        .iter()
+                        .map(|(line_no, line)| {
+                            substitute(line, &called.params, args)
+                                .map(|line| (*line_no, line))
+                                .with_context(|| format!("in {} at line {}", name, line_no))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    for (line_no, line) in body {
+                        self.line(&line, depth + 1)
+                            .with_context(|| format!("in {} at line {}", name, line_no))?;
+                    }
+                    Ok(())
+                }
+                _ => self.push_line(line),
+            }
+        }
+    }
+
+    pub fn assemble(set: &InstructionSet, source: &str) -> Result<Assembled> {
+        let mut assembler = Assembler {
+            set,
+            macros: HashMap::new(),
+            out: Assembled {
+                program: Vec::new(),
+                labels: Vec::new(),
+            },
+            cycle: 1,
+            registers: set.registers().clone(),
+        };
+
+        let mut defining: Option<(String, Macro)> = None;
+        for (idx, line) in source.lines().enumerate() {
+            let line_no = idx + 1;
+            let line = line.split(';').next().unwrap_or_default().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match (words.as_slice(), &mut defining) {
+                (["macro", ..], Some(_)) => {
+                    return Err(anyhow!(
+                        "line {}: macros can't be defined in macros",
+                        line_no
+                    ));
+                }
+                (["macro", name, params @ ..], None) => {
+                    if !is_identifier(name) || set.find(name).is_some() {
+                        return Err(anyhow!("line {}: bad macro name {:?}", line_no, name));
+                    }
+                    if let Some(param) = params.iter().find(|p| !is_identifier(p)) {
+                        return Err(anyhow!("line {}: bad parameter {:?}", line_no, param));
+                    }
+                    let params = params.iter().map(|p| p.to_string()).collect();
+                    let body = Vec::new();
+                    defining = Some((name.to_string(), Macro { params, body }));
+                }
+                (["endm"], Some(_)) => {
+                    let (name, defined) = defining.take().unwrap();
+                    assembler.macros.insert(name, defined);
+                }
+                (_, Some(_)) if line.contains(':') => {
+                    return Err(anyhow!("line {}: labels can't go in macros", line_no));
+                }
+                (_, Some((_, defined))) => defined.body.push((line_no, line.to_string())),
+                (_, None) => assembler
+                    .line(line, 0)
+                    .with_context(|| format!("line {}", line_no))?,
+            }
+        }
+        if let Some((name, _)) = defining {
+            return Err(anyhow!("macro {} is missing its endm", name));
+        }
+        Ok(assembler.out)
+    }
+
+    /// Where an instruction sits in a run of the program.
+    pub struct Entry {
+        pub pc: usize,
+        pub start: u32,
+        pub cycles: u32,
+        /// The registers for every cycle of the instruction
+        pub registers: Registers,
+    }
+
+    impl Entry {
+        /// The cycles it covers that count towards the signal strength.
+        pub fn special(&self) -> impl Iterator<Item = u32> {
+            (self.start..self.start + self.cycles).filter(|cycle| super::is_special(*cycle))
+        }
+    }
+
+    pub fn entries(set: &InstructionSet, program: &[Instruction]) -> Vec<Entry> {
+        let mut registers = set.registers().clone();
+        let mut start = 1;
+        let mut out = Vec::with_capacity(program.len());
+        for (pc, instruction) in program.iter().enumerate() {
+            let cycles = set.opcode(instruction).cycles;
+            out.push(Entry {
+                pc,
+                start,
+                cycles,
+                registers: registers.clone(),
+            });
+            set.apply(&mut registers, instruction);
+            start += cycles;
+        }
+        out
+    }
+
+    /// A listing of the program with each instruction's start cycle and
+    /// registers, plus any special cycles it covers, in comments so that it
+    /// assembles back into the same program.
+    pub fn listing(
+        set: &InstructionSet,
+        program: &[Instruction],
+        labels: &[(String, usize)],
+    ) -> String {
+        let mut out = String::new();
+        for entry in entries(set, program) {
+            for (label, _) in labels.iter().filter(|(_, pc)| *pc == entry.pc) {
+                out.push_str(&format!("{}:\n", label));
+            }
+            out.push_str(&format!(
+                "    {:<12} ; @{:<4} cycle {:>4}  {}",
+                set.format(&program[entry.pc]),
+                entry.pc,
+                entry.start,
+                entry.registers
+            ));
+            for cycle in entry.special() {
+                out.push_str(&format!(
+                    "  special {} ({})",
+                    cycle,
+                    cycle as i64 * entry.registers.get(X)
+                ));
+            }
+            out.push('\n');
+        }
+        for (label, _) in labels.iter().filter(|(_, pc)| *pc == program.len()) {
+            out.push_str(&format!("{}:\n", label));
+        }
+        out
+    }
+}
+
+use vm::{Instruction, InstructionSet, Machine, X};
+
+fn parse(set: &InstructionSet, input: &str) -> Result<Vec<Instruction>> {
+    set.parse(input).context("Failed to parse input")
 }
 
 fn is_special(cycle: u32) -> bool {
@@ -38,46 +715,496 @@ fn is_special(cycle: u32) -> bool {
     }
 }
 
-fn run_program<F: FnMut(u32, i64)>(commands: &[Command], callback: &mut F) {
-    let mut cycle: u32 = 1;
-    let mut register: i64 = 1;
+fn run_program<F: FnMut(&vm::Cycle)>(set: &InstructionSet, program: &[Instruction], hook: F) {
+    let mut machine = Machine::new(set, program);
+    machine.hook(hook);
+    machine.run();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Breakpoint {
+    /// Before this cycle runs
+    Cycle(u32),
+    /// Before the first cycle of the instruction at this index
+    Instruction(usize),
+}
+
+impl FromStr for Breakpoint {
+    type Err = anyhow::Error;
+
+    /// `20` for a cycle or `@3` for an instruction index.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.strip_prefix('@') {
+            Some(idx) => Ok(Breakpoint::Instruction(
+                idx.parse()
+                    .with_context(|| format!("Bad instruction {:?}", s))?,
+            )),
+            None => Ok(Breakpoint::Cycle(
+                s.parse().with_context(|| format!("Bad cycle {:?}", s))?,
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Cycle(cycle) => write!(f, "cycle {}", cycle),
+            Breakpoint::Instruction(idx) => write!(f, "instruction @{}", idx),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Compare {
+    const ALL: [(&'static str, Compare); 6] = [
+        ("==", Compare::Eq),
+        ("!=", Compare::Ne),
+        ("<=", Compare::Le),
+        (">=", Compare::Ge),
+        ("<", Compare::Lt),
+        (">", Compare::Gt),
+    ];
+
+    fn holds(&self, left: i64, right: i64) -> bool {
+        match self {
+            Compare::Eq => left == right,
+            Compare::Ne => left != right,
+            Compare::Lt => left < right,
+            Compare::Le => left <= right,
+            Compare::Gt => left > right,
+            Compare::Ge => left >= right,
+        }
+    }
+}
+
+/// Stops the debugger when a register starts to meet a condition, or on any
+/// change to it when there is no condition.
+struct Watch {
+    text: String,
+    register: vm::Register,
+    condition: Option<(Compare, i64)>,
+}
+
+impl Watch {
+    /// `x` to watch for any change, or something like `x>=20`.
+    fn parse(set: &InstructionSet, s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (name, condition) = match Compare::ALL
+            .iter()
+            .find_map(|(op, compare)| s.split_once(op).map(|(name, value)| (name, compare, value)))
+        {
+            Some((name, compare, value)) => (
+                name.trim(),
+                Some((
+                    *compare,
+                    value
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("Bad value in watch {:?}", s))?,
+                )),
+            ),
+            None => (s, None),
+        };
+        let register = set
+            .registers()
+            .find(name)
+            .ok_or_else(|| anyhow!("Unknown register {:?}", name))?;
+        Ok(Self {
+            text: s.to_string(),
+            register,
+            condition,
+        })
+    }
+
+    fn fired(&self, before: &vm::Registers, after: &vm::Registers) -> bool {
+        let (before, after) = (before.get(self.register), after.get(self.register));
+        match self.condition {
+            Some((compare, value)) => !compare.holds(before, value) && compare.holds(after, value),
+            None => before != after,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stop {
+    Breakpoint(Breakpoint),
+    Watch(String),
+    Halted,
+}
+
+impl std::fmt::Display for Stop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stop::Breakpoint(breakpoint) => write!(f, "hit breakpoint at {}", breakpoint),
+            Stop::Watch(watch) => write!(f, "watch {} triggered", watch),
+            Stop::Halted => write!(f, "program finished"),
+        }
+    }
+}
+
+/// Steps a machine a cycle at a time, stopping at breakpoints and watches and
+/// optionally tracing every cycle it runs.
+struct Debugger<'a> {
+    set: &'a InstructionSet,
+    machine: Machine<'a>,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<Watch>,
+    trace: bool,
+    /// Whether any cycle has run yet, as breakpoints are otherwise only
+    /// checked after running one
+    started: bool,
+}
+
+impl<'a> Debugger<'a> {
+    fn new(set: &'a InstructionSet, machine: Machine<'a>) -> Self {
+        Self {
+            set,
+            machine,
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            trace: false,
+            started: false,
+        }
+    }
+
+    fn watch(&mut self, s: &str) -> Result<()> {
+        self.watches.push(Watch::parse(self.set, s)?);
+        Ok(())
+    }
+
+    /// The cycle about to run, or None once the program has finished.
+    fn trace_line(&self) -> Option<String> {
+        let cycle = self.machine.next_cycle()?;
+        Some(format!(
+            "cycle {:>4}  @{:<4} {:<12} {}/{}  {}",
+            cycle.number,
+            cycle.pc,
+            self.set.format(cycle.instruction),
+            cycle.elapsed + 1,
+            self.set.opcode(cycle.instruction).cycles,
+            cycle.registers
+        ))
+    }
+
+    fn at_breakpoint(&self) -> Option<Breakpoint> {
+        self.breakpoints
+            .iter()
+            .copied()
+            .find(|breakpoint| match breakpoint {
+                Breakpoint::Cycle(cycle) => self.machine.cycle() == *cycle,
+                Breakpoint::Instruction(idx) => {
+                    self.machine.pc() == *idx && self.machine.elapsed() == 0
+                }
+            })
+    }
+
+    /// Runs one cycle, returning why the debugger should stop after it if it
+    /// should.
+    fn step<W: Write>(&mut self, out: &mut W) -> Result<Option<Stop>> {
+        let Some(line) = self.trace_line() else {
+            return Ok(Some(Stop::Halted));
+        };
+        let before = self.machine.registers().clone();
+        self.machine.step();
+        self.started = true;
+        if self.trace {
+            writeln!(out, "{}", line)?;
+        }
+
+        let after = self.machine.registers();
+        if let Some(watch) = self.watches.iter().find(|w| w.fired(&before, after)) {
+            return Ok(Some(Stop::Watch(watch.text.clone())));
+        }
+        if self.machine.halted() {
+            return Ok(Some(Stop::Halted));
+        }
+        Ok(self.at_breakpoint().map(Stop::Breakpoint))
+    }
+
+    /// Runs until something stops it. Other than a breakpoint on the very
+    /// first cycle, this always runs at least one cycle.
+    fn resume<W: Write>(&mut self, out: &mut W) -> Result<Stop> {
+        if !self.started {
+            self.started = true;
+            if let Some(breakpoint) = self.at_breakpoint() {
+                return Ok(Stop::Breakpoint(breakpoint));
+            }
+        }
+        loop {
+            if let Some(stop) = self.step(out)? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    /// Runs the rest of the program without stopping, noting each
+    /// breakpoint or watch it passes in with the trace.
+    fn finish<W: Write>(&mut self, out: &mut W) -> Result<()> {
+        loop {
+            let stop = self.resume(out)?;
+            if stop == Stop::Halted {
+                return Ok(());
+            }
+            writeln!(out, "{}", stop)?;
+        }
+    }
 
-    for command in commands {
-        match command {
-            Command::Noop => {
-                callback(cycle, register);
-                cycle += 1;
+    fn show_next<W: Write>(&self, out: &mut W) -> Result<()> {
+        if let Some(line) = self.trace_line() {
+            writeln!(out, "next: {}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Reads commands until `quit` or the end of the input:
+    ///
+    /// * `s [n]`/`step [n]` runs n cycles, 1 by default
+    /// * `c`/`continue` runs to the next breakpoint or watch
+    /// * `b 20`/`b @3` breaks at a cycle or instruction
+    /// * `w x`/`w x>=20` watches a register
+    /// * `t`/`trace` toggles the trace
+    /// * `r`/`regs` shows the registers and `i`/`info` everything set
+    fn repl<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> Result<()> {
+        self.show_next(out)?;
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            let mut words = line.split_whitespace();
+            let result = match (words.next(), words.next()) {
+                (None, _) => continue,
+                (Some("q" | "quit"), _) => break,
+                (Some("s" | "step"), count) => match count.map(str::parse::<usize>).transpose() {
+                    Ok(count) => {
+                        for _ in 0..count.unwrap_or(1) {
+                            if let Some(stop) = self.step(out)? {
+                                writeln!(out, "{}", stop)?;
+                                break;
+                            }
+                        }
+                        self.show_next(out)
+                    }
+                    Err(e) => Err(anyhow!("Bad count {:?}: {}", count.unwrap_or_default(), e)),
+                },
+                (Some("c" | "continue"), _) => {
+                    let stop = self.resume(out)?;
+                    writeln!(out, "{}", stop)?;
+                    self.show_next(out)
+                }
+                (Some("b" | "break"), Some(at)) => at.parse().map(|breakpoint| {
+                    self.breakpoints.push(breakpoint);
+                }),
+                (Some("w" | "watch"), Some(_)) => {
+                    let condition = line.split_once(char::is_whitespace).unwrap().1;
+                    self.watch(condition)
+                }
+                (Some("t" | "trace"), _) => {
+                    self.trace = !self.trace;
+                    Ok(())
+                }
+                (Some("r" | "regs"), _) => {
+                    writeln!(out, "{}", self.machine.registers())?;
+                    Ok(())
+                }
+                (Some("i" | "info"), _) => {
+                    for breakpoint in &self.breakpoints {
+                        writeln!(out, "break at {}", breakpoint)?;
+                    }
+                    for watch in &self.watches {
+                        writeln!(out, "watch {}", watch.text)?;
+                    }
+                    Ok(())
+                }
+                _ => Err(anyhow!("Unknown command {:?}", line)),
+            };
+            if let Err(e) = result {
+                writeln!(out, "error: {:#}", e)?;
             }
-            Command::Addx(x) => {
-                callback(cycle, register);
-                cycle += 1;
-                callback(cycle, register);
-                cycle += 1;
-                register += *x as i64;
+        }
+        Ok(())
+    }
+
+    fn into_machine(self) -> Machine<'a> {
+        self.machine
+    }
+}
+
+const SCREEN_WIDTH: usize = 40;
+const SCREEN_HEIGHT: usize = 6;
+
+/// Where the sprite has to be while the CRT draws two neighbouring pixels,
+/// the left one in column `col`.
+fn sprite_for(col: i64, left: bool, right: bool) -> i64 {
+    match (left, right) {
+        (true, true) => col,
+        (true, false) => col - 1,
+        (false, true) => col + 2,
+        (false, false) => col + 3,
+    }
+}
+
+/// A `noop`/`addx` program that makes the CRT spell out `text`.
+fn synthesize(text: &str) -> Result<String> {
+    let letters: Vec<char> = text.chars().collect();
+    if letters.len() * ocr::WIDTH > SCREEN_WIDTH {
+        return Err(anyhow!(
+            "{:?} won't fit, the screen only has room for {} letters",
+            text,
+            SCREEN_WIDTH / ocr::WIDTH
+        ));
+    }
+    let glyphs = letters
+        .iter()
+        .map(|letter| ocr::glyph(*letter).ok_or_else(|| anyhow!("No glyph for {:?}", letter)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut pixels = vec![false; SCREEN_WIDTH * SCREEN_HEIGHT];
+    for (idx, glyph) in glyphs.iter().enumerate() {
+        for (row, line) in glyph.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                pixels[row * SCREEN_WIDTH + idx * ocr::WIDTH + col] = c == '#';
             }
         }
     }
+
+    // Every addx holds the sprite still for two cycles and can then move it
+    // anywhere, so pairs of pixels can always be drawn together. The screen
+    // is an even width so no pair wraps onto the next row. The one pair that
+    // can't be chosen is the first, drawn before any addx has finished.
+    if !(pixels[0] && pixels[1]) {
+        let what = match letters.first() {
+            Some(letter) => format!("{:?} first", letter),
+            None => "an empty screen".to_string(),
+        };
+        return Err(anyhow!(
+            "can't draw {}: the sprite starts at x=1 and can't move before cycle 3, \
+             so the top row always starts with two lit pixels",
+            what
+        ));
+    }
+    let mut sprites: Vec<i64> = pixels
+        .chunks(2)
+        .enumerate()
+        .map(|(idx, pair)| sprite_for(((idx * 2) % SCREEN_WIDTH) as i64, pair[0], pair[1]))
+        .collect();
+    // Both 0 and 1 light the first pair and X starts at 1
+    sprites[0] = 1;
+
+    let mut program = String::new();
+    for (idx, sprite) in sprites.iter().enumerate() {
+        let next = sprites.get(idx + 1).unwrap_or(sprite);
+        if next == sprite {
+            program.push_str("noop\nnoop\n");
+        } else {
+            program.push_str(&format!("addx {}\n", next - sprite));
+        }
+    }
+    Ok(program)
+}
+
+/// `cargo run --bin 10 -- --raw` answers part two with the CRT itself rather
+/// than the letters on it.
+///
+/// `--asm` reads the input as extended assembly (see `asm`) for both parts,
+/// and `--listing` prints it back annotated with cycles and registers.
+///
+/// `--synthesize WORD` prints a program that spells out `WORD` on the CRT.
+///
+/// `--trace` prints every cycle part one runs, and `--debug` steps through it
+/// with commands read from stdin, stopping first at any `--break 20`,
+/// `--break @3` or `--watch x>=20` given. Without `--debug` those are only
+/// noted in the trace, which carries on to the end either way.
+struct Options {
+    asm: bool,
+    listing: bool,
+    raw: bool,
+    synthesize: Option<String>,
+    trace: bool,
+    debug: bool,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<String>,
+}
+
+impl Options {
+    /// The program in the input along with any labels it has.
+    fn load(&self, set: &InstructionSet, input: &str) -> Result<asm::Assembled> {
+        if self.asm {
+            return asm::assemble(set, input).context("Failed to assemble input");
+        }
+        Ok(asm::Assembled {
+            program: parse(set, input)?,
+            labels: Vec::new(),
+        })
+    }
+
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            asm: args.contains("--asm"),
+            listing: args.contains("--listing"),
+            raw: args.contains("--raw"),
+            synthesize: args.opt_value_from_str("--synthesize")?,
+            trace: args.contains("--trace"),
+            debug: args.contains("--debug"),
+            breakpoints: args.values_from_str("--break")?,
+            watches: args.values_from_str("--watch")?,
+        })
+    }
 }
+
+aoc_solutions::day_options!(Options);
+
 pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<i64>, anyhow::Error> {
-    let commands = parse(input).context("Failed to parse input")?;
+    let set = InstructionSet::device();
+    let options = Options::get()?;
+    let assembled = options.load(&set, input)?;
+    let program = assembled.program;
+    if options.listing {
+        print!("{}", asm::listing(&set, &program, &assembled.labels));
+    }
     let mut signal_strength = 0;
 
-    run_program(&commands, &mut |cycle, register| {
-        if is_special(cycle) {
-            signal_strength += register * cycle as i64;
+    let mut machine = Machine::new(&set, &program);
+    machine.hook(|cycle| {
+        if is_special(cycle.number) {
+            signal_strength += cycle.registers.get(X) * cycle.number as i64;
         }
     });
+    if options.trace || options.debug {
+        let mut debugger = Debugger::new(&set, machine);
+        debugger.trace = options.trace;
+        debugger.breakpoints = options.breakpoints.clone();
+        for watch in &options.watches {
+            debugger.watch(watch)?;
+        }
+        let mut out = std::io::stdout().lock();
+        if options.debug {
+            debugger.repl(std::io::stdin().lock(), &mut out)?;
+        }
+        debugger.finish(&mut out)?;
+        machine = debugger.into_machine();
+    }
+    machine.run();
+    drop(machine);
 
     Ok(Some(signal_strength))
 }
 
-pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<String>, anyhow::Error> {
-    let commands = parse(input).context("Failed to parse input")?;
-
+/// The CRT as rows of `#` and `.`.
+fn render_screen(set: &InstructionSet, program: &[Instruction]) -> String {
     let mut out = String::new();
     let mut current = String::new();
-    run_program(&commands, &mut |cycle, register| {
-        let cycle = (cycle - 1) % 40;
+    run_program(set, program, |cycle| {
+        let register = cycle.registers.get(X);
+        let cycle = (cycle.number - 1) % 40;
         if cycle as i64 == register - 1 || cycle as i64 == register || cycle as i64 == register + 1
         {
             current.push('#');
@@ -90,7 +1217,23 @@ pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<String>, anyho
             current = String::new();
         }
     });
-    Ok(Some(out))
+    out
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<String>, anyhow::Error> {
+    let set = InstructionSet::device();
+    let options = Options::get()?;
+    let program = options.load(&set, input)?.program;
+    let screen = render_screen(&set, &program);
+    if let Some(text) = &options.synthesize {
+        print!("{}", synthesize(text)?);
+    }
+    if options.raw {
+        return Ok(Some(screen));
+    }
+    ocr::decode(&screen)
+        .with_context(|| format!("Failed to read the CRT:\n{}", screen))
+        .map(Some)
 }
 
 #[cfg(test)]
@@ -121,8 +1264,346 @@ mod tests_day_10 {
         );
         let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
         assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
-        let result = part_two(input, RunType::Example)?;
+        let set = InstructionSet::device();
+        let result = Some(render_screen(&set, &parse(&set, input)?));
         assert_eq!(result, expected);
+        // The example doesn't spell anything
+        assert!(part_two(input, RunType::Example).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vm() -> anyhow::Result<()> {
+        let set = InstructionSet::device();
+        assert!(set.parse("addx").is_err());
+        assert!(set.parse("addx 1 2").is_err());
+        assert!(set.parse("addx y").is_err());
+        assert!(set.parse("jmp 1").is_err());
+
+        let program = set.parse("addx 3\nnoop\naddx x")?;
+        let mut machine = Machine::new(&set, &program);
+        assert!(machine.step());
+        assert_eq!(
+            (machine.cycle(), machine.pc(), machine.elapsed()),
+            (2, 0, 1)
+        );
+        assert_eq!(machine.registers().get(X), 1);
+        assert!(machine.step());
+        assert_eq!(
+            (machine.cycle(), machine.pc(), machine.elapsed()),
+            (3, 1, 0)
+        );
+        assert_eq!(machine.registers().get(X), 4);
+        machine.run();
+        assert!(machine.halted());
+        assert!(!machine.step());
+        assert_eq!(machine.cycle(), 6);
+        assert_eq!(machine.registers().get(X), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_vm_extensions() -> anyhow::Result<()> {
+        let set = InstructionSet::device()
+            .with(vm::Opcode {
+                name: "mul",
+                cycles: 3,
+                arity: 1,
+                effect: |registers, args| {
+                    let value = registers.get(X) * registers.value(&args[0]);
+                    registers.set(X, value);
+                },
+            })
+            .with(vm::Opcode {
+                name: "noop",
+                cycles: 2,
+                arity: 0,
+                effect: |_, _| {},
+            });
+        let program = set.parse("mul 5\nnoop\naddx -2")?;
+        assert_eq!(set.format(&program[2]), "addx -2");
+
+        let mut seen = Vec::new();
+        run_program(&set, &program, |cycle| {
+            seen.push((cycle.number, cycle.pc, cycle.registers.get(X)));
+        });
+        assert_eq!(
+            seen,
+            vec![
+                (1, 0, 1),
+                (2, 0, 1),
+                (3, 0, 1),
+                (4, 1, 5),
+                (5, 1, 5),
+                (6, 2, 5),
+                (7, 2, 5)
+            ]
+        );
+
+        let set = vm::InstructionSet::new(&[("a", 0), ("b", 7)]).with(vm::Opcode {
+            name: "mov",
+            cycles: 1,
+            arity: 2,
+            effect: |registers, args| {
+                if let vm::Operand::Register(target) = args[0] {
+                    registers.set(target, registers.value(&args[1]));
+                }
+            },
+        });
+        let program = set.parse("mov a b\nmov b 2")?;
+        let mut machine = Machine::new(&set, &program);
+        machine.run();
+        assert_eq!(machine.registers().to_string(), "a=7 b=2");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr() -> anyhow::Result<()> {
+        let word = "ZYPHERS";
+        let mut screen = String::new();
+        for row in 0..ocr::HEIGHT {
+            for letter in word.chars() {
+                let line = ocr::glyph(letter).unwrap()[row];
+                screen.push_str(&format!("{:.<width$}", line, width = ocr::WIDTH));
+            }
+            screen.push_str(".....\n");
+        }
+        assert_eq!(ocr::decode(&screen)?, word);
+        assert_eq!(ocr::decode(&screen.replace('#', "."))?, "");
+
+        let smudged = screen.replacen('.', "#", 1);
+        let error = ocr::decode(&smudged).unwrap_err().to_string();
+        assert!(error.starts_with("unknown glyph in columns 0..5:\n#####"));
+        assert!(ocr::decode("#\n#").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_debugger() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let set = InstructionSet::device();
+        let program = parse(&set, input)?;
+        let mut history = vec![0];
+        run_program(&set, &program, |cycle| history.push(cycle.registers.get(X)));
+
+        let mut out = Vec::new();
+        let mut debugger = Debugger::new(&set, Machine::new(&set, &program));
+        assert_eq!(
+            debugger.trace_line().unwrap(),
+            "cycle    1  @0    addx 15      1/2  x=1"
+        );
+        debugger.breakpoints.push("20".parse()?);
+        debugger.breakpoints.push("@3".parse()?);
+        assert!(debugger.watch("y").is_err());
+        assert!(debugger.watch("x<z").is_err());
+
+        assert_eq!(
+            debugger.resume(&mut out)?,
+            Stop::Breakpoint(Breakpoint::Instruction(3))
+        );
+        assert_eq!(debugger.machine.cycle(), 7);
+        assert_eq!(
+            debugger.resume(&mut out)?,
+            Stop::Breakpoint(Breakpoint::Cycle(20))
+        );
+        assert_eq!(debugger.machine.registers().get(X), history[20]);
+
+        // Cycle n sees x from history[n] so the watch fires as the value for
+        // the next cycle changes
+        debugger.watch("x")?;
+        let first = (21..history.len()).find(|cycle| history[*cycle] != history[cycle - 1]);
+        assert_eq!(debugger.resume(&mut out)?, Stop::Watch("x".into()));
+        assert_eq!(Some(debugger.machine.cycle() as usize), first);
+        assert!(out.is_empty());
+
+        debugger.trace = true;
+        assert_eq!(debugger.step(&mut out)?, None);
+        let trace = String::from_utf8(out)?;
+        assert!(trace.starts_with(&format!("cycle {:>4}", first.unwrap())));
+
+        // Finishing traces every cycle, breakpoints and all
+        let mut debugger = Debugger::new(&set, Machine::new(&set, &program));
+        debugger.trace = true;
+        debugger.breakpoints.push("20".parse()?);
+        let mut out = Vec::new();
+        debugger.finish(&mut out)?;
+        let trace = String::from_utf8(out)?;
+        assert_eq!(
+            trace.lines().filter(|l| l.starts_with("cycle")).count(),
+            240
+        );
+        assert!(trace.contains("\ncycle   19 "));
+        assert!(trace.contains("\nhit breakpoint at cycle 20\ncycle   20 "));
+        assert!(debugger.machine.halted());
+        Ok(())
+    }
+
+    #[test]
+    fn test_debugger_repl() -> anyhow::Result<()> {
+        let set = InstructionSet::device();
+        let program = set.parse("noop\naddx 3\naddx -5\nnoop")?;
+        let mut debugger = Debugger::new(&set, Machine::new(&set, &program));
+        let commands = "b @2\n  w x<0\ninfo\nc\ns 2\nregs\nbogus\nc\nc\nq\n";
+        let mut out = Vec::new();
+        debugger.repl(commands.as_bytes(), &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "next: cycle    1  @0    noop         1/1  x=1
+break at instruction @2
+watch x<0
+hit breakpoint at instruction @2
+next: cycle    4  @2    addx -5      1/2  x=4
+watch x<0 triggered
+next: cycle    6  @3    noop         1/1  x=-1
+x=-1
+error: Unknown command \"bogus\"
+program finished
+program finished
+"
+        );
+
+        // A breakpoint on the first cycle stops before anything runs, and a
+        // bad count is reported without ending the session
+        let mut debugger = Debugger::new(&set, Machine::new(&set, &program));
+        let mut out = Vec::new();
+        debugger.repl("b 1\nc\ns x\ns\nc\n".as_bytes(), &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "next: cycle    1  @0    noop         1/1  x=1
+hit breakpoint at cycle 1
+next: cycle    1  @0    noop         1/1  x=1
+error: Bad count \"x\": invalid digit found in string
+next: cycle    2  @1    addx 3       1/2  x=1
+program finished
+"
+        );
+        Ok(())
+    }
+
+    fn round_trip(text: &str) -> anyhow::Result<String> {
+        let set = InstructionSet::device();
+        let program = set.parse(&synthesize(text)?)?;
+        ocr::decode(&render_screen(&set, &program))
+    }
+
+    #[test]
+    fn test_synthesize() -> anyhow::Result<()> {
+        assert_eq!(round_trip("EFBPRZ")?, "EFBPRZ");
+        // Everything can follow a letter that starts with two lit pixels
+        let letters: String = ocr::GLYPHS.iter().map(|(c, _)| *c).collect();
+        for chunk in letters.as_bytes().chunks(7) {
+            let text = format!("E{}", std::str::from_utf8(chunk)?);
+            assert_eq!(round_trip(&text)?, text);
+        }
+
+        let error = synthesize("ABC").unwrap_err().to_string();
+        assert!(error.starts_with("can't draw 'A' first: the sprite starts at x=1"));
+        assert!(synthesize("").is_err());
+        assert!(synthesize("EQ").is_err());
+        assert!(synthesize("EEEEEEEEE").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_assembler() -> anyhow::Result<()> {
+        let set = InstructionSet::device();
+        let source = "; draws nothing in particular
+macro bump by
+    addx $by ; up
+    addx -$by
+endm
+macro twice by
+    bump $by
+    bump $by
+endm
+
+start: noop
+    twice 3
+loop:
+    set 10
+    wait 2
+    until 16
+    addx x
+end:
+";
+        let assembled = asm::assemble(&set, source)?;
+        let plain = "noop
+addx 3
+addx -3
+addx 3
+addx -3
+addx 9
+noop
+noop
+noop
+noop
+addx 10";
+        assert_eq!(assembled.program, set.parse(plain)?);
+        assert_eq!(
+            assembled.labels,
+            vec![
+                ("start".to_string(), 0),
+                ("loop".to_string(), 5),
+                ("end".to_string(), 11)
+            ]
+        );
+
+        let listing = asm::listing(&set, &assembled.program, &assembled.labels);
+        assert!(listing.starts_with("start:\n    noop         ; @0    cycle    1  x=1\n"));
+        assert!(listing.contains("    addx 10      ; @10   cycle   16  x=10\nend:\n"));
+        let again = asm::assemble(&set, &listing)?;
+        assert_eq!(again.program, assembled.program);
+        assert_eq!(again.labels, assembled.labels);
+
+        // Parameters are replaced whole even when one starts with another
+        let overlap = "macro pair a ab\n    addx $a\n    addx $ab\nendm\npair 2 5\npair 1 -1";
+        assert_eq!(
+            asm::assemble(&set, overlap)?.program,
+            set.parse("addx 2\naddx 5\naddx 1\naddx -1")?
+        );
+
+        for bad in [
+            "jmp 3",
+            "1abc: noop",
+            "a:\na:",
+            "macro m\nnoop",
+            "macro m\nmacro n\nendm\nendm",
+            "macro noop\nendm",
+            "macro m a\nendm\nm",
+            "macro m\nm\nendm\nm",
+            "macro m a-b\nendm",
+            "macro m a\nwait $b\nendm\nm 1",
+            "macro m\nhere: noop\nendm",
+            "wait 2\nuntil 2",
+        ] {
+            assert!(asm::assemble(&set, bad).is_err(), "{:?}", bad);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_special_cycles() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let set = InstructionSet::device();
+        let program = parse(&set, input)?;
+        let entries = asm::entries(&set, &program);
+        let mut total = 0;
+        let mut covered = Vec::new();
+        for entry in &entries {
+            for cycle in entry.special() {
+                covered.push(cycle);
+                total += cycle as i64 * entry.registers.get(X);
+            }
+        }
+        assert_eq!(covered, vec![20, 60, 100, 140, 180, 220]);
+        assert_eq!(Some(total), part_one(input, RunType::Example)?);
+
+        let listing = asm::listing(&set, &program, &[]);
+        let line = listing.lines().find(|l| l.contains("special 20")).unwrap();
+        assert_eq!(
+            line,
+            "    addx -1      ; @10   cycle   20  x=21  special 20 (420)"
+        );
         Ok(())
     }
 }
@@ -0,0 +1,333 @@
+advent_of_code::solution!(11);
+
+use advent_of_code::template::RunType;
+
+use anyhow::{anyhow, Context, Result};
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, multispace0, space0, u64 as number},
+    combinator::{all_consuming, map, value},
+    multi::{many1, separated_list1},
+    sequence::{delimited, preceded, terminated, tuple},
+    Finish, IResult,
+};
+use num_bigint::BigUint;
+use num_traits::Num;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Old,
+    Value(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Mul,
+}
+
+/// The right hand side of `new = old * 19` and friends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Operation {
+    left: Operand,
+    operator: Operator,
+    right: Operand,
+}
+
+impl Operation {
+    fn apply<T: Num + Clone + From<u64>>(&self, old: &T) -> T {
+        let value = |operand: &Operand| match operand {
+            Operand::Old => old.clone(),
+            Operand::Value(value) => T::from(*value),
+        };
+        let (left, right) = (value(&self.left), value(&self.right));
+        match self.operator {
+            Operator::Add => left + right,
+            Operator::Mul => left * right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Monkey {
+    items: Vec<u64>,
+    operation: Operation,
+    divisor: u64,
+    if_true: usize,
+    if_false: usize,
+}
+
+fn operand(input: &str) -> IResult<&str, Operand> {
+    alt((value(Operand::Old, tag("old")), map(number, Operand::Value)))(input)
+}
+
+fn operation(input: &str) -> IResult<&str, Operation> {
+    map(
+        tuple((
+            preceded(tag("new = "), operand),
+            delimited(
+                space0,
+                alt((
+                    value(Operator::Add, char('+')),
+                    value(Operator::Mul, char('*')),
+                )),
+                space0,
+            ),
+            operand,
+        )),
+        |(left, operator, right)| Operation {
+            left,
+            operator,
+            right,
+        },
+    )(input)
+}
+
+/// A line of the notes, ignoring the indentation.
+fn line<'a, O, F>(label: &'static str, parser: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+{
+    delimited(tuple((space0, tag(label))), parser, multispace0)
+}
+
+fn monkey(input: &str) -> IResult<&str, (u64, Monkey)> {
+    map(
+        tuple((
+            line("Monkey ", terminated(number, char(':'))),
+            line("Starting items: ", separated_list1(tag(", "), number)),
+            line("Operation: ", operation),
+            line("Test: divisible by ", number),
+            line("If true: throw to monkey ", number),
+            line("If false: throw to monkey ", number),
+        )),
+        |(id, items, operation, divisor, if_true, if_false)| {
+            (
+                id,
+                Monkey {
+                    items,
+                    operation,
+                    divisor,
+                    if_true: if_true as usize,
+                    if_false: if_false as usize,
+                },
+            )
+        },
+    )(input)
+}
+
+fn parse(input: &str) -> Result<Vec<Monkey>> {
+    let (_, parsed) = all_consuming(preceded(multispace0, many1(monkey)))(input)
+        .finish()
+        .map_err(|e| anyhow!("Failed to parse input: {}", e))?;
+
+    let count = parsed.len();
+    let mut monkeys = Vec::with_capacity(count);
+    for (idx, (id, monkey)) in parsed.into_iter().enumerate() {
+        if id != idx as u64 {
+            return Err(anyhow!("Expected monkey {} but found monkey {}", idx, id));
+        }
+        if monkey.divisor == 0 {
+            return Err(anyhow!("Monkey {} tests divisibility by 0", id));
+        }
+        for target in [monkey.if_true, monkey.if_false] {
+            if target >= count || target == idx {
+                return Err(anyhow!("Monkey {} can't throw to monkey {}", id, target));
+            }
+        }
+        monkeys.push(monkey);
+    }
+    Ok(monkeys)
+}
+
+/// What keeps worry levels in check after each inspection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Relief {
+    /// Part one's relief that the item wasn't damaged
+    DivideBy(u64),
+    /// Only keeping the level modulo a multiple of every divisor, which
+    /// leaves every test with the same result
+    Modulo(u64),
+    /// Letting the levels grow as big as they like
+    None,
+}
+
+impl Relief {
+    fn apply<T: Num + From<u64>>(&self, level: T) -> T {
+        match self {
+            Relief::DivideBy(by) => level / T::from(*by),
+            Relief::Modulo(modulus) => level % T::from(*modulus),
+            Relief::None => level,
+        }
+    }
+}
+
+/// The product of every monkey's divisor. They're all primes in the puzzle
+/// so there's nothing to gain from a least common multiple.
+fn divisor_product(monkeys: &[Monkey]) -> Result<u64> {
+    monkeys.iter().try_fold(1_u64, |product, monkey| {
+        product
+            .checked_mul(monkey.divisor)
+            .ok_or_else(|| anyhow!("Product of the divisors doesn't fit in a u64"))
+    })
+}
+
+/// How many items each monkey inspects over `rounds` rounds, with worry
+/// levels held as `T`.
+fn simulate<T: Num + Clone + From<u64>>(
+    monkeys: &[Monkey],
+    rounds: usize,
+    relief: Relief,
+) -> Vec<usize> {
+    let mut items: Vec<Vec<T>> = monkeys
+        .iter()
+        .map(|monkey| monkey.items.iter().map(|item| T::from(*item)).collect())
+        .collect();
+    let divisors: Vec<T> = monkeys
+        .iter()
+        .map(|monkey| T::from(monkey.divisor))
+        .collect();
+    let mut inspected = vec![0; monkeys.len()];
+
+    for _ in 0..rounds {
+        for (idx, monkey) in monkeys.iter().enumerate() {
+            let held = std::mem::take(&mut items[idx]);
+            inspected[idx] += held.len();
+            for item in held {
+                let level = relief.apply(monkey.operation.apply(&item));
+                let target = if (level.clone() % divisors[idx].clone()).is_zero() {
+                    monkey.if_true
+                } else {
+                    monkey.if_false
+                };
+                items[target].push(level);
+            }
+        }
+    }
+    inspected
+}
+
+fn monkey_business(inspected: &[usize]) -> usize {
+    let mut inspected = inspected.to_vec();
+    inspected.sort_unstable_by(|a, b| b.cmp(a));
+    inspected.iter().take(2).product()
+}
+
+/// `cargo run --bin 11 -- --reference 20` also runs part two for that many
+/// rounds with unbounded `BigUint` levels and checks the reduced run against
+/// it. Levels get squared as they go, so keep the round count small.
+struct Options {
+    reference: Option<usize>,
+}
+
+impl Options {
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            reference: args.opt_value_from_str("--reference")?,
+        })
+    }
+}
+
+aoc_solutions::day_options!(Options);
+
+/// Runs the reduced and unbounded simulations side by side.
+fn check_reduction(monkeys: &[Monkey], rounds: usize) -> Result<Vec<usize>> {
+    let modulus = divisor_product(monkeys)?;
+    let reduced = simulate::<u128>(monkeys, rounds, Relief::Modulo(modulus));
+    let reference = simulate::<BigUint>(monkeys, rounds, Relief::None);
+    if reduced != reference {
+        return Err(anyhow!(
+            "Reduced levels gave {:?} but the reference gave {:?} after {} rounds",
+            reduced,
+            reference,
+            rounds
+        ));
+    }
+    Ok(reference)
+}
+
+pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let monkeys = parse(input)?;
+    let inspected = simulate::<u64>(&monkeys, 20, Relief::DivideBy(3));
+    Ok(Some(monkey_business(&inspected)))
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let monkeys = parse(input)?;
+    if let Some(rounds) = Options::get()?.reference {
+        let inspected = check_reduction(&monkeys, rounds).context("Reduction check failed")?;
+        println!("reference after {} rounds: {:?}", rounds, inspected);
+    }
+    // Levels stay below the modulus, which fits in a u64, so squaring one
+    // always fits in a u128
+    let modulus = divisor_product(&monkeys)?;
+    let inspected = simulate::<u128>(&monkeys, 10000, Relief::Modulo(modulus));
+    Ok(Some(monkey_business(&inspected)))
+}
+
+#[cfg(test)]
+mod tests_day_11 {
+    use super::*;
+
+    #[test]
+    fn test_part_one() -> anyhow::Result<()> {
+        let expected = Some(10605);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(expected.is_none() || !input.is_empty(), "example 1 empty!");
+        let result = part_one(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two() -> anyhow::Result<()> {
+        let expected = Some(2713310158);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
+        let result = part_two(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let monkeys = parse(input)?;
+        assert_eq!(monkeys.len(), 4);
+        assert_eq!(
+            monkeys[2],
+            Monkey {
+                items: vec![79, 60, 97],
+                operation: Operation {
+                    left: Operand::Old,
+                    operator: Operator::Mul,
+                    right: Operand::Old,
+                },
+                divisor: 13,
+                if_true: 1,
+                if_false: 3,
+            }
+        );
+        assert_eq!(monkeys[1].operation.apply(&5_u64), 11);
+        assert_eq!(divisor_product(&monkeys)?, 96577);
+
+        assert!(parse(&input.replace("Monkey 1:", "Monkey 5:")).is_err());
+        assert!(parse(&input.replace("throw to monkey 3", "throw to monkey 4")).is_err());
+        assert!(parse(&input.replace("old + 6", "old - 6")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reduction_matches_reference() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let monkeys = parse(input)?;
+        assert_eq!(check_reduction(&monkeys, 1)?, vec![2, 4, 3, 6]);
+        assert_eq!(check_reduction(&monkeys, 20)?, vec![99, 97, 8, 103]);
+        assert_eq!(
+            simulate::<BigUint>(&monkeys, 20, Relief::DivideBy(3)),
+            simulate::<u64>(&monkeys, 20, Relief::DivideBy(3))
+        );
+        Ok(())
+    }
+}
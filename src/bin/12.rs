@@ -0,0 +1,330 @@
+advent_of_code::solution!(12);
+
+use advent_of_code::template::RunType;
+
+use anyhow::{anyhow, Context, Result};
+use aoc_lib::grid::{Direction, Location, Map};
+use std::collections::BTreeMap;
+
+/// Shortest paths over any graph given as closures.
+mod search {
+    use std::cmp::Reverse;
+    use std::collections::{BTreeMap, BinaryHeap};
+
+    pub struct Paths<N> {
+        distances: BTreeMap<N, u64>,
+        previous: BTreeMap<N, N>,
+        /// The first goal reached, which is the closest one
+        pub found: Option<N>,
+    }
+
+    impl<N: Ord + Clone> Paths<N> {
+        pub fn distance(&self, node: &N) -> Option<u64> {
+            self.distances.get(node).copied()
+        }
+
+        /// Every node from the source the search reached `node` from up to
+        /// `node` itself.
+        pub fn path_to(&self, node: &N) -> Option<Vec<N>> {
+            self.distance(node)?;
+            let mut path = vec![node.clone()];
+            while let Some(prev) = self.previous.get(path.last().unwrap()) {
+                path.push(prev.clone());
+            }
+            path.reverse();
+            Some(path)
+        }
+    }
+
+    /// Dijkstra from every one of `sources` at once, stopping at the first
+    /// node `is_goal` accepts. `cost` is called for each edge `neighbors`
+    /// gives, so with a cost of 1 this is a breadth first search.
+    pub fn shortest_paths<N, I, F, C, G>(
+        sources: impl IntoIterator<Item = N>,
+        mut neighbors: F,
+        mut cost: C,
+        mut is_goal: G,
+    ) -> Paths<N>
+    where
+        N: Ord + Clone,
+        I: IntoIterator<Item = N>,
+        F: FnMut(&N) -> I,
+        C: FnMut(&N, &N) -> u64,
+        G: FnMut(&N) -> bool,
+    {
+        let mut paths = Paths {
+            distances: BTreeMap::new(),
+            previous: BTreeMap::new(),
+            found: None,
+        };
+        let mut queue = BinaryHeap::new();
+        for source in sources {
+            paths.distances.insert(source.clone(), 0);
+            queue.push(Reverse((0, source)));
+        }
+
+        while let Some(Reverse((distance, node))) = queue.pop() {
+            if paths.distances.get(&node) != Some(&distance) {
+                continue;
+            }
+            if is_goal(&node) {
+                paths.found = Some(node);
+                break;
+            }
+            for next in neighbors(&node) {
+                let next_distance = distance + cost(&node, &next);
+                let better = match paths.distances.get(&next) {
+                    Some(known) => next_distance < *known,
+                    None => true,
+                };
+                if better {
+                    paths.distances.insert(next.clone(), next_distance);
+                    paths.previous.insert(next.clone(), node.clone());
+                    queue.push(Reverse((next_distance, next)));
+                }
+            }
+        }
+        paths
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Start,
+    End,
+    Plain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Square {
+    height: u8,
+    kind: Kind,
+}
+
+struct Hill {
+    map: Map<Square>,
+    start: Location,
+    end: Location,
+}
+
+impl Hill {
+    fn parse(input: &str) -> Result<Self> {
+        let map = Map::parse(input, |c| {
+            let (height, kind) = match c {
+                'S' => ('a', Kind::Start),
+                'E' => ('z', Kind::End),
+                'a'..='z' => (c, Kind::Plain),
+                other => return Err(anyhow!("Unexpected square {:?}", other)),
+            };
+            Ok(Square {
+                height: height as u8 - b'a',
+                kind,
+            })
+        })
+        .context("failed to parse input")?;
+
+        let find = |kind| {
+            map.iter()
+                .flatten()
+                .find(|(_, square)| square.kind == kind)
+                .map(|(location, _)| location)
+        };
+        let start = find(Kind::Start).context("No start square")?;
+        let end = find(Kind::End).context("No end square")?;
+        Ok(Self { map, start, end })
+    }
+
+    /// Squares one step away that can be climbed to from `location`, or
+    /// climbed from when going `backwards`.
+    fn neighbors(&self, location: &Location, backwards: bool) -> Vec<Location> {
+        let height = self.map.get(location).height;
+        Direction::all()
+            .iter()
+            .filter_map(|direction| self.map.go_direction(location, direction))
+            .filter(|next| {
+                let next_height = self.map.get(next).height;
+                if backwards {
+                    height <= next_height + 1
+                } else {
+                    next_height <= height + 1
+                }
+            })
+            .collect()
+    }
+
+    /// The squares on the quickest way up from the start.
+    fn climb(&self) -> Option<Vec<Location>> {
+        let paths = search::shortest_paths(
+            [self.start.clone()],
+            |location| self.neighbors(location, false),
+            |_, _| 1,
+            |location| *location == self.end,
+        );
+        paths.path_to(paths.found.as_ref()?)
+    }
+
+    /// The squares on the quickest way up from any square at the lowest
+    /// height, found with one search going back down from the end.
+    fn hike(&self) -> Option<Vec<Location>> {
+        let paths = search::shortest_paths(
+            [self.end.clone()],
+            |location| self.neighbors(location, true),
+            |_, _| 1,
+            |location| self.map.get(location).height == 0,
+        );
+        let mut path = paths.path_to(paths.found.as_ref()?)?;
+        path.reverse();
+        Some(path)
+    }
+
+    /// The map with `path` drawn on it as arrows, each pointing to the next
+    /// square, like the puzzle does.
+    fn draw(&self, path: &[Location]) -> String {
+        let mut arrows = BTreeMap::new();
+        for step in path.windows(2) {
+            let arrow = Direction::all()
+                .iter()
+                .find(|direction| {
+                    self.map.go_direction(&step[0], direction).as_ref() == Some(&step[1])
+                })
+                .map(|direction| match direction {
+                    Direction::North => '^',
+                    Direction::East => '>',
+                    Direction::South => 'v',
+                    Direction::West => '<',
+                })
+                .unwrap_or('?');
+            arrows.insert(step[0].clone(), arrow);
+        }
+
+        let mut out = String::new();
+        for row in self.map.iter() {
+            for (location, square) in row {
+                out.push(match arrows.get(&location) {
+                    Some(arrow) => *arrow,
+                    None if square.kind == Kind::End => 'E',
+                    None => '.',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// `cargo run --bin 12 -- --draw` prints the path each part takes.
+struct Options {
+    draw: bool,
+}
+
+impl Options {
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            draw: args.contains("--draw"),
+        })
+    }
+}
+
+aoc_solutions::day_options!(Options);
+
+fn steps(hill: &Hill, path: Option<Vec<Location>>, options: &Options) -> Option<usize> {
+    if let Some(path) = &path {
+        if options.draw {
+            println!("{}", hill.draw(path));
+        }
+    }
+    path.map(|path| path.len() - 1)
+}
+
+pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let hill = Hill::parse(input)?;
+    Ok(steps(&hill, hill.climb(), Options::get()?))
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let hill = Hill::parse(input)?;
+    Ok(steps(&hill, hill.hike(), Options::get()?))
+}
+
+#[cfg(test)]
+mod tests_day_12 {
+    use super::*;
+
+    #[test]
+    fn test_part_one() -> anyhow::Result<()> {
+        let expected = Some(31);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(expected.is_none() || !input.is_empty(), "example 1 empty!");
+        let result = part_one(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two() -> anyhow::Result<()> {
+        let expected = Some(29);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
+        let result = part_two(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hike_matches_forward_search() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        let hill = Hill::parse(input)?;
+        let lowest: Vec<Location> = hill
+            .map
+            .iter()
+            .flatten()
+            .filter(|(_, square)| square.height == 0)
+            .map(|(location, _)| location)
+            .collect();
+        let forward = search::shortest_paths(
+            lowest,
+            |location| hill.neighbors(location, false),
+            |_, _| 1,
+            |location| *location == hill.end,
+        );
+        assert_eq!(forward.distance(&hill.end), Some(29));
+
+        let hike = hill.hike().unwrap();
+        assert_eq!(hill.map.get(&hike[0]).height, 0);
+        assert_eq!(hike.last(), Some(&hill.end));
+
+        // Charging for every climb makes the flat way round cheaper
+        let climbs = search::shortest_paths(
+            [hill.start.clone()],
+            |location| hill.neighbors(location, false),
+            |from, to| 1 + 10 * (hill.map.get(to).height > hill.map.get(from).height) as u64,
+            |location| *location == hill.end,
+        );
+        assert_eq!(climbs.distance(&hill.end), Some(31 + 10 * 25));
+        Ok(())
+    }
+
+    #[test]
+    fn test_draw() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let hill = Hill::parse(input)?;
+        let drawing = hill.draw(&hill.climb().unwrap());
+        let grid: Vec<Vec<char>> = drawing.lines().map(|l| l.chars().collect()).collect();
+        assert_eq!(grid.len(), 5);
+        assert_eq!(drawing.matches(['^', '>', 'v', '<']).count(), 31);
+
+        // Following the arrows from the start leads to the end
+        let (mut row, mut col) = (0_usize, 0_usize);
+        for _ in 0..31 {
+            match grid[row][col] {
+                '^' => row -= 1,
+                'v' => row += 1,
+                '>' => col += 1,
+                '<' => col -= 1,
+                other => panic!("fell off the path at {:?}", other),
+            }
+        }
+        assert_eq!(grid[row][col], 'E');
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+advent_of_code::solution!(13);
+
+use advent_of_code::template::RunType;
+
+use anyhow::{anyhow, Context, Result};
+use nom::{
+    branch::alt,
+    character::complete::{char, u32 as number},
+    combinator::{all_consuming, map},
+    multi::separated_list0,
+    sequence::delimited,
+    Finish, IResult,
+};
+use std::cmp::Ordering;
+
+/// Packets are equal when they sort to the same place, so `[[2]]` and `2`
+/// are equal even though they print differently.
+#[derive(Debug, Clone)]
+enum Packet {
+    Int(u32),
+    List(Vec<Packet>),
+}
+
+impl Ord for Packet {
+    /// Lists compare item by item with the shorter one first on a tie, and an
+    /// integer compared against a list acts as a list of just itself.
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Packet::Int(left), Packet::Int(right)) => left.cmp(right),
+            (Packet::List(left), Packet::List(right)) => left.cmp(right),
+            (Packet::Int(_), Packet::List(right)) => std::slice::from_ref(self).cmp(right),
+            (Packet::List(left), Packet::Int(_)) => {
+                left.as_slice().cmp(std::slice::from_ref(other))
+            }
+        }
+    }
+}
+
+impl PartialOrd for Packet {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Packet {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Packet {}
+
+impl std::fmt::Display for Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Packet::Int(value) => write!(f, "{}", value),
+            Packet::List(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+fn packet(input: &str) -> IResult<&str, Packet> {
+    alt((
+        map(number, Packet::Int),
+        map(
+            delimited(char('['), separated_list0(char(','), packet), char(']')),
+            Packet::List,
+        ),
+    ))(input)
+}
+
+impl std::str::FromStr for Packet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (_, packet) = all_consuming(packet)(s)
+            .finish()
+            .map_err(|e| anyhow!("Bad packet {:?}: {}", s, e))?;
+        Ok(packet)
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<(Packet, Packet)>> {
+    input
+        .trim()
+        .split("\n\n")
+        .enumerate()
+        .map(|(idx, pair)| {
+            let lines: Vec<&str> = pair.lines().collect();
+            match lines.as_slice() {
+                [left, right] => Ok((left.parse()?, right.parse()?)),
+                _ => Err(anyhow!("Expected two packets but got {}", lines.len())),
+            }
+            .with_context(|| format!("Failed to parse pair {}", idx + 1))
+        })
+        .collect()
+}
+
+fn divider(value: u32) -> Packet {
+    Packet::List(vec![Packet::List(vec![Packet::Int(value)])])
+}
+
+pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let pairs = parse(input)?;
+    Ok(Some(
+        pairs
+            .iter()
+            .enumerate()
+            .filter(|(_, (left, right))| left < right)
+            .map(|(idx, _)| idx + 1)
+            .sum(),
+    ))
+}
+
+/// Where each divider would end up if they were sorted in with `packets`,
+/// counting from 1. Each one goes after every packet smaller than it, along
+/// with the dividers before it, so there's no need to sort anything. None of
+/// the packets should compare equal to a divider.
+fn divider_indices(packets: &[Packet], dividers: &[Packet]) -> Vec<usize> {
+    let mut dividers: Vec<&Packet> = dividers.iter().collect();
+    dividers.sort();
+    dividers
+        .iter()
+        .enumerate()
+        .map(|(idx, divider)| packets.iter().filter(|packet| packet < divider).count() + idx + 1)
+        .collect()
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let packets: Vec<Packet> = parse(input)?
+        .into_iter()
+        .flat_map(|(left, right)| [left, right])
+        .collect();
+    let indices = divider_indices(&packets, &[divider(2), divider(6)]);
+    Ok(Some(indices.iter().product()))
+}
+
+#[cfg(test)]
+mod tests_day_13 {
+    use super::*;
+    use aoc_solutions::random::Lcg;
+
+    #[test]
+    fn test_part_one() -> anyhow::Result<()> {
+        let expected = Some(13);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(expected.is_none() || !input.is_empty(), "example 1 empty!");
+        let result = part_one(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two() -> anyhow::Result<()> {
+        let expected = Some(140);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
+        let result = part_two(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    fn random_packet(rng: &mut Lcg, depth: u32) -> Packet {
+        if depth == 0 || rng.below(3) == 0 {
+            Packet::Int(rng.below(12) as u32)
+        } else {
+            let len = rng.below(5);
+            Packet::List((0..len).map(|_| random_packet(rng, depth - 1)).collect())
+        }
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let mut printed = String::new();
+        for (left, right) in parse(input)? {
+            printed.push_str(&format!("{}\n{}\n\n", left, right));
+        }
+        assert_eq!(printed.trim_end(), input.trim_end());
+
+        let mut rng = Lcg::new(13);
+        for _ in 0..500 {
+            // Equality ignores the difference between `[[2]]` and `2`, so
+            // compare what gets printed instead
+            let printed = random_packet(&mut rng, 4).to_string();
+            assert_eq!(printed.parse::<Packet>()?.to_string(), printed);
+        }
+        for bad in ["", "[", "[1,]", "[1 2]", "[[1]]]", "[-1]"] {
+            assert!(bad.parse::<Packet>().is_err(), "{:?}", bad);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dividers_match_sort() -> anyhow::Result<()> {
+        let mut rng = Lcg::new(6);
+        let dividers = [divider(6), divider(2), "[3,[]]".parse()?];
+        let packets: Vec<Packet> = (0..300)
+            .map(|_| random_packet(&mut rng, 3))
+            .filter(|packet| dividers.iter().all(|d| packet != d))
+            .collect();
+
+        let mut sorted = packets.clone();
+        sorted.extend(dividers.iter().cloned());
+        sorted.sort();
+        let mut expected: Vec<usize> = dividers
+            .iter()
+            .map(|d| sorted.iter().position(|p| p == d).unwrap() + 1)
+            .collect();
+        expected.sort();
+        assert_eq!(divider_indices(&packets, &dividers), expected);
+
+        assert!("[[1],[2,3,4]]".parse::<Packet>()? < "[[1],4]".parse()?);
+        assert!("[9]".parse::<Packet>()? > "[[8,7,6]]".parse()?);
+        assert!("[1,1]".parse::<Packet>()? > "[1]".parse()?);
+        assert_eq!("[[4]]".parse::<Packet>()?, "4".parse()?);
+        assert_ne!("[[4]]".parse::<Packet>()?, "[4,4]".parse()?);
+        Ok(())
+    }
+}
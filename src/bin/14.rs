@@ -0,0 +1,276 @@
+advent_of_code::solution!(14);
+
+use advent_of_code::template::RunType;
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+
+/// `x,y` as the scan gives them, with `y` counting down from the source
+/// at the top of the cave.
+type Point = (i32, i32);
+
+const SOURCE: Point = (500, 0);
+
+fn parse(input: &str) -> Result<Vec<Vec<Point>>> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| {
+            line.split(" -> ")
+                .map(|point| {
+                    let (x, y) = point
+                        .split_once(',')
+                        .ok_or_else(|| anyhow!("Expected x,y but got {:?}", point))?;
+                    Ok((x.trim().parse()?, y.trim().parse()?))
+                })
+                .collect::<Result<Vec<Point>>>()
+                .with_context(|| format!("Failed to parse line {}", idx + 1))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Rock,
+    Sand,
+}
+
+/// Only the squares holding something are stored, so the cave can be as
+/// wide as the sand needs without allocating a grid for it.
+struct Cave {
+    tiles: HashMap<Point, Tile>,
+    /// The lowest row with any rock in it
+    bottom: i32,
+    /// An endless floor of rock on this row, if there is one
+    floor: Option<i32>,
+}
+
+impl Cave {
+    fn new(paths: &[Vec<Point>]) -> Result<Self> {
+        let mut tiles = HashMap::new();
+        for path in paths {
+            if let [only] = path.as_slice() {
+                tiles.insert(*only, Tile::Rock);
+            }
+            for line in path.windows(2) {
+                let ((x1, y1), (x2, y2)) = (line[0], line[1]);
+                if x1 != x2 && y1 != y2 {
+                    return Err(anyhow!(
+                        "Rock from {:?} to {:?} isn't straight",
+                        line[0],
+                        line[1]
+                    ));
+                }
+                for x in x1.min(x2)..=x1.max(x2) {
+                    for y in y1.min(y2)..=y1.max(y2) {
+                        tiles.insert((x, y), Tile::Rock);
+                    }
+                }
+            }
+        }
+        let bottom = tiles
+            .keys()
+            .map(|(_, y)| *y)
+            .max()
+            .context("No rocks in the cave")?;
+        Ok(Self {
+            tiles,
+            bottom,
+            floor: None,
+        })
+    }
+
+    fn with_floor(mut self) -> Self {
+        self.floor = Some(self.bottom + 2);
+        self
+    }
+
+    fn blocked(&self, point: &Point) -> bool {
+        self.floor == Some(point.1) || self.tiles.contains_key(point)
+    }
+
+    /// Pours sand in until it either falls past every rock or piles up to
+    /// the source, returning how many grains came to rest.
+    ///
+    /// Every grain follows the one before it until the spot where that one
+    /// stopped, so rather than dropping each from the source this keeps the
+    /// path the last grain took and starts the next from the end of it.
+    fn pour(&mut self) -> usize {
+        let mut rested = 0;
+        let mut path = vec![SOURCE];
+        while let Some(&(x, y)) = path.last() {
+            if self.floor.is_none() && y > self.bottom {
+                break;
+            }
+            let next = [(x, y + 1), (x - 1, y + 1), (x + 1, y + 1)]
+                .into_iter()
+                .find(|next| !self.blocked(next));
+            match next {
+                Some(next) => path.push(next),
+                None => {
+                    self.tiles.insert((x, y), Tile::Sand);
+                    rested += 1;
+                    path.pop();
+                }
+            }
+        }
+        rested
+    }
+
+    /// Draws everything in the cave along with the source, using the
+    /// puzzle's characters.
+    fn render(&self) -> String {
+        let points = self.tiles.keys().chain(std::iter::once(&SOURCE));
+        let (mut min_x, mut max_x, mut max_y) = (SOURCE.0, SOURCE.0, SOURCE.1);
+        for (x, y) in points {
+            min_x = min_x.min(*x);
+            max_x = max_x.max(*x);
+            max_y = max_y.max(*y);
+        }
+        if let Some(floor) = self.floor {
+            max_y = max_y.max(floor);
+        }
+
+        let mut out = String::new();
+        for y in 0..=max_y {
+            for x in min_x..=max_x {
+                out.push(match self.tiles.get(&(x, y)) {
+                    Some(Tile::Rock) => '#',
+                    Some(Tile::Sand) => 'o',
+                    None if (x, y) == SOURCE => '+',
+                    None if self.floor == Some(y) => '#',
+                    None => '.',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// `cargo run --bin 14 -- --render` prints the cave once the sand stops.
+struct Options {
+    render: bool,
+}
+
+impl Options {
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            render: args.contains("--render"),
+        })
+    }
+}
+
+aoc_solutions::day_options!(Options);
+
+fn pour(mut cave: Cave, options: &Options) -> usize {
+    let rested = cave.pour();
+    if options.render {
+        println!("{}", cave.render());
+    }
+    rested
+}
+
+pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let cave = Cave::new(&parse(input)?)?;
+    Ok(Some(pour(cave, Options::get()?)))
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    let cave = Cave::new(&parse(input)?)?.with_floor();
+    Ok(Some(pour(cave, Options::get()?)))
+}
+
+#[cfg(test)]
+mod tests_day_14 {
+    use super::*;
+
+    #[test]
+    fn test_part_one() -> anyhow::Result<()> {
+        let expected = Some(24);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(expected.is_none() || !input.is_empty(), "example 1 empty!");
+        let result = part_one(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two() -> anyhow::Result<()> {
+        let expected = Some(93);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
+        let result = part_two(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    /// Drops every grain from the source, like the puzzle describes.
+    fn naive_pour(cave: &mut Cave) -> usize {
+        let mut rested = 0;
+        while !cave.blocked(&SOURCE) {
+            let (mut x, mut y) = SOURCE;
+            loop {
+                if cave.floor.is_none() && y > cave.bottom {
+                    return rested;
+                }
+                match [(x, y + 1), (x - 1, y + 1), (x + 1, y + 1)]
+                    .into_iter()
+                    .find(|next| !cave.blocked(next))
+                {
+                    Some(next) => (x, y) = next,
+                    None => break,
+                }
+            }
+            cave.tiles.insert((x, y), Tile::Sand);
+            rested += 1;
+        }
+        rested
+    }
+
+    #[test]
+    fn test_matches_naive() -> anyhow::Result<()> {
+        let input = "495,3 -> 505,3\n490,8 -> 497,8 -> 497,6\n503,10 -> 512,10 -> 512,7\n500,14";
+        let paths = parse(input)?;
+        for floor in [false, true] {
+            let (mut fast, mut naive) = (Cave::new(&paths)?, Cave::new(&paths)?);
+            if floor {
+                fast = fast.with_floor();
+                naive = naive.with_floor();
+            }
+            assert_eq!(fast.pour(), naive_pour(&mut naive));
+            assert_eq!(fast.render(), naive.render());
+        }
+        assert!(Cave::new(&parse("1,1 -> 2,2")?).is_err());
+        assert!(parse("1,1 -> 2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_render() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let mut cave = Cave::new(&parse(input)?)?;
+        cave.pour();
+        assert_eq!(
+            cave.render(),
+            "......+...
+..........
+......o...
+.....ooo..
+....#ooo##
+...o#ooo#.
+..###ooo#.
+....oooo#.
+.o.ooooo#.
+#########.
+"
+        );
+
+        let mut cave = Cave::new(&parse(input)?)?.with_floor();
+        cave.pour();
+        let render = cave.render();
+        assert!(render.starts_with("..........o..........\n"));
+        assert!(render.ends_with("\nooooo.......ooooooooo\n#####################\n"));
+        Ok(())
+    }
+}
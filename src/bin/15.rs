@@ -0,0 +1,295 @@
+advent_of_code::solution!(15);
+
+use advent_of_code::template::RunType;
+
+use anyhow::{anyhow, Context, Result};
+use nom::{
+    bytes::complete::tag,
+    character::complete::i64 as number,
+    combinator::{all_consuming, map},
+    sequence::{preceded, separated_pair},
+    Finish, IResult,
+};
+use std::collections::BTreeSet;
+
+/// `x` then `y` as the sensors report them. Part one fixes `y` and sweeps
+/// `x`, so `y` is the row.
+type Point = (i64, i64);
+
+fn distance(a: &Point, b: &Point) -> i64 {
+    (a.0 - b.0).abs() + (a.1 - b.1).abs()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sensor {
+    position: Point,
+    beacon: Point,
+}
+
+impl Sensor {
+    /// How far the sensor can see, as there can't be another beacon any
+    /// closer than the one it found.
+    fn radius(&self) -> i64 {
+        distance(&self.position, &self.beacon)
+    }
+
+    fn covers(&self, point: &Point) -> bool {
+        distance(&self.position, point) <= self.radius()
+    }
+
+    /// The columns this sensor covers on `row`, both ends included.
+    fn span(&self, row: i64) -> Option<(i64, i64)> {
+        let reach = self.radius() - (self.position.1 - row).abs();
+        (reach >= 0).then_some((self.position.0 - reach, self.position.0 + reach))
+    }
+}
+
+fn point(input: &str) -> IResult<&str, Point> {
+    separated_pair(
+        preceded(tag("x="), number),
+        tag(", "),
+        preceded(tag("y="), number),
+    )(input)
+}
+
+fn sensor(input: &str) -> IResult<&str, Sensor> {
+    map(
+        separated_pair(
+            preceded(tag("Sensor at "), point),
+            tag(": closest beacon is at "),
+            point,
+        ),
+        |(position, beacon)| Sensor { position, beacon },
+    )(input)
+}
+
+fn parse(input: &str) -> Result<Vec<Sensor>> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| {
+            all_consuming(sensor)(line.trim())
+                .finish()
+                .map(|(_, sensor)| sensor)
+                .map_err(|e| anyhow!("Failed to parse line {}: {}", idx + 1, e))
+        })
+        .collect()
+}
+
+/// The puzzle asks about different places for the example and the real
+/// input.
+struct Bounds {
+    /// The row part one counts
+    row: i64,
+    /// The largest coordinate the distress beacon can have
+    limit: i64,
+}
+
+impl Bounds {
+    fn new(run_type: RunType) -> Self {
+        match run_type {
+            RunType::Example => Self { row: 10, limit: 20 },
+            RunType::Real => Self {
+                row: 2_000_000,
+                limit: 4_000_000,
+            },
+        }
+    }
+}
+
+/// Merges the spans every sensor covers on `row` into sorted, disjoint
+/// ranges. Ranges that touch end to end are joined too.
+fn coverage(sensors: &[Sensor], row: i64) -> Vec<(i64, i64)> {
+    let mut spans: Vec<(i64, i64)> = sensors.iter().filter_map(|s| s.span(row)).collect();
+    spans.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Where the distress beacon could be. It has to be the only uncovered
+/// square in the search area, so every neighbour it has in the area is
+/// covered and it sits just outside the edge of a sensor next to each one.
+/// Every edge lies on a line where either `x + y` or `x - y` is fixed.
+///
+/// Away from the sides of the area that means it's where two of those lines
+/// cross. On a side it might only touch one sensor's edge, so the lines are
+/// crossed with the sides too, and the corners are checked in case the area
+/// is a single square.
+fn find_beacon(sensors: &[Sensor], limit: i64) -> Option<Point> {
+    let mut rising = BTreeSet::new();
+    let mut falling = BTreeSet::new();
+    for sensor in sensors {
+        let (x, y) = sensor.position;
+        let reach = sensor.radius() + 1;
+        rising.extend([x + y - reach, x + y + reach]);
+        falling.extend([x - y - reach, x - y + reach]);
+    }
+
+    let crossings = rising.iter().flat_map(|sum| {
+        falling.iter().filter_map(move |difference| {
+            let both = sum + difference;
+            let parity = both.rem_euclid(2);
+            (parity == 0).then_some((both / 2, (sum - difference) / 2))
+        })
+    });
+    let sides = rising
+        .iter()
+        .flat_map(|sum| {
+            [
+                (0, *sum),
+                (limit, sum - limit),
+                (*sum, 0),
+                (sum - limit, limit),
+            ]
+        })
+        .chain(falling.iter().flat_map(|difference| {
+            [
+                (0, -difference),
+                (limit, limit - difference),
+                (*difference, 0),
+                (difference + limit, limit),
+            ]
+        }));
+    let corners = [(0, 0), (0, limit), (limit, 0), (limit, limit)];
+    crossings
+        .chain(sides)
+        .chain(corners)
+        .filter(|(x, y)| (0..=limit).contains(x) && (0..=limit).contains(y))
+        .find(|point| sensors.iter().all(|sensor| !sensor.covers(point)))
+}
+
+fn tuning_frequency((x, y): Point) -> i64 {
+    x * 4_000_000 + y
+}
+
+pub fn part_one(input: &str, run_type: RunType) -> Result<Option<i64>, anyhow::Error> {
+    let sensors = parse(input)?;
+    let row = Bounds::new(run_type).row;
+    let spans = coverage(&sensors, row);
+    let covered: i64 = spans.iter().map(|(start, end)| end - start + 1).sum();
+    let beacons: BTreeSet<Point> = sensors
+        .iter()
+        .map(|sensor| sensor.beacon)
+        .filter(|(x, y)| *y == row && spans.iter().any(|(s, e)| (s..=e).contains(&x)))
+        .collect();
+    Ok(Some(covered - beacons.len() as i64))
+}
+
+pub fn part_two(input: &str, run_type: RunType) -> Result<Option<i64>, anyhow::Error> {
+    let sensors = parse(input)?;
+    let limit = Bounds::new(run_type).limit;
+    let beacon = find_beacon(&sensors, limit).context("Every square is covered")?;
+    Ok(Some(tuning_frequency(beacon)))
+}
+
+#[cfg(test)]
+mod tests_day_15 {
+    use super::*;
+
+    #[test]
+    fn test_part_one() -> anyhow::Result<()> {
+        let expected = Some(26);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(expected.is_none() || !input.is_empty(), "example 1 empty!");
+        let result = part_one(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two() -> anyhow::Result<()> {
+        let expected = Some(56000011);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
+        let result = part_two(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_coverage() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let sensors = parse(input)?;
+        assert_eq!(
+            sensors[6],
+            Sensor {
+                position: (8, 7),
+                beacon: (2, 10)
+            }
+        );
+        assert_eq!(coverage(&sensors, 10), vec![(-2, 24)]);
+        assert_eq!(coverage(&sensors, 11), vec![(-3, 13), (15, 25)]);
+        assert_eq!(coverage(&sensors, -100), vec![]);
+
+        // Checking square by square gives the same answer
+        for row in -5..30 {
+            let spans = coverage(&sensors, row);
+            for x in -15..40 {
+                let covered = sensors.iter().any(|s| s.covers(&(x, row)));
+                let in_span = spans.iter().any(|(s, e)| (*s..=*e).contains(&x));
+                assert_eq!(covered, in_span, "{},{}", x, row);
+            }
+        }
+        assert!(parse("Sensor at x=1, y=2: closest beacon is at x=3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_beacon_matches_scan() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        let sensors = parse(input)?;
+        let limit = 20;
+        let scanned = (0..=limit).find_map(|row| {
+            coverage(&sensors, row)
+                .iter()
+                .find(|(_, end)| (0..limit).contains(end))
+                .map(|(_, end)| (end + 1, row))
+        });
+        assert_eq!(scanned, Some((14, 11)));
+        assert_eq!(find_beacon(&sensors, limit), scanned);
+
+        // A beacon tucked in a corner only touches one sensor's edge
+        let corner = vec![Sensor {
+            position: (0, 0),
+            beacon: (0, 9),
+        }];
+        assert_eq!(find_beacon(&corner, 5), Some((5, 5)));
+
+        // One on a side of the area only touches one sensor's edge too, so
+        // try it against every side by flipping the sensors round
+        let limit = 5;
+        let side = [((5, 8), (5, 3)), ((4, 0), (4, 5)), ((0, 1), (0, 6))];
+        let flips: [fn(Point) -> Point; 8] = [
+            |(x, y)| (x, y),
+            |(x, y)| (5 - x, y),
+            |(x, y)| (x, 5 - y),
+            |(x, y)| (5 - x, 5 - y),
+            |(x, y)| (y, x),
+            |(x, y)| (5 - y, x),
+            |(x, y)| (y, 5 - x),
+            |(x, y)| (5 - y, 5 - x),
+        ];
+        for flip in flips {
+            let sensors: Vec<Sensor> = side
+                .iter()
+                .map(|(position, beacon)| Sensor {
+                    position: flip(*position),
+                    beacon: flip(*beacon),
+                })
+                .collect();
+            let uncovered: Vec<Point> = (0..=limit)
+                .flat_map(|x| (0..=limit).map(move |y| (x, y)))
+                .filter(|point| sensors.iter().all(|sensor| !sensor.covers(point)))
+                .collect();
+            assert_eq!(uncovered, vec![flip((2, 5))]);
+            assert_eq!(find_beacon(&sensors, limit), Some(flip((2, 5))));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+advent_of_code::solution!(16);
+
+use advent_of_code::template::RunType;
+
+use anyhow::{anyhow, Context, Result};
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{alpha1, u32 as number},
+    combinator::{all_consuming, map},
+    multi::separated_list1,
+    sequence::{preceded, tuple},
+    Finish, IResult,
+};
+use petgraph::algo::{astar, dijkstra};
+use petgraph::graph::{DiGraph, NodeIndex};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Valve {
+    name: String,
+    flow: u32,
+}
+
+fn line(input: &str) -> IResult<&str, (Valve, Vec<&str>)> {
+    map(
+        tuple((
+            preceded(tag("Valve "), alpha1::<&str, _>),
+            preceded(tag(" has flow rate="), number),
+            preceded(
+                alt((
+                    tag("; tunnels lead to valves "),
+                    tag("; tunnel leads to valve "),
+                )),
+                separated_list1(tag(", "), alpha1),
+            ),
+        )),
+        |(name, flow, tunnels)| {
+            let name = name.to_string();
+            (Valve { name, flow }, tunnels)
+        },
+    )(input)
+}
+
+fn parse(input: &str) -> Result<Vec<(Valve, Vec<&str>)>> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(idx, text)| {
+            all_consuming(line)(text.trim())
+                .finish()
+                .map(|(_, parsed)| parsed)
+                .map_err(|e| anyhow!("Failed to parse line {}: {}", idx + 1, e))
+        })
+        .collect()
+}
+
+/// A valve that gets opened and the minute it happens in. It releases
+/// pressure from the next minute on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Opening {
+    valve: usize,
+    minute: u32,
+}
+
+/// The tunnels along with a smaller graph of only the valves worth
+/// opening. Moving between those is all that matters for the score, so
+/// the searches only ever look at that.
+struct Volcano {
+    graph: DiGraph<Valve, ()>,
+    start: NodeIndex,
+    /// Valves with a non-zero flow, which index the bits of every mask
+    useful: Vec<NodeIndex>,
+    /// Minutes to walk from one useful valve to another, with an extra
+    /// last row for walking from the start
+    distances: Vec<Vec<u32>>,
+}
+
+impl Volcano {
+    fn new(input: &str) -> Result<Self> {
+        let parsed = parse(input)?;
+        let mut graph = DiGraph::new();
+        let mut nodes = HashMap::new();
+        for (valve, _) in &parsed {
+            let name = valve.name.clone();
+            if nodes.insert(name, graph.add_node(valve.clone())).is_some() {
+                return Err(anyhow!("Valve {} appears twice", valve.name));
+            }
+        }
+        for (valve, tunnels) in &parsed {
+            for tunnel in tunnels {
+                let to = nodes
+                    .get(*tunnel)
+                    .with_context(|| format!("{} leads to unknown valve {}", valve.name, tunnel))?;
+                graph.add_edge(nodes[&valve.name], *to, ());
+            }
+        }
+        let start = *nodes.get("AA").context("No valve AA to start from")?;
+
+        let useful: Vec<NodeIndex> = graph
+            .node_indices()
+            .filter(|node| graph[*node].flow > 0)
+            .collect();
+        if useful.len() >= u64::BITS as usize {
+            return Err(anyhow!("Too many valves to fit in a mask"));
+        }
+        let distances = useful
+            .iter()
+            .chain(std::iter::once(&start))
+            .map(|from| {
+                let reached = dijkstra(&graph, *from, None, |_| 1_u32);
+                useful
+                    .iter()
+                    .map(|to| reached.get(to).copied().unwrap_or(u32::MAX))
+                    .collect()
+            })
+            .collect();
+        Ok(Self {
+            graph,
+            start,
+            useful,
+            distances,
+        })
+    }
+
+    /// The index of the start in `distances`.
+    fn start_idx(&self) -> usize {
+        self.useful.len()
+    }
+
+    fn flow(&self, valve: usize) -> u32 {
+        self.graph[self.useful[valve]].flow
+    }
+
+    fn name(&self, node: NodeIndex) -> &str {
+        &self.graph[node].name
+    }
+
+    /// A mask with every useful valve in it.
+    fn all(&self) -> u64 {
+        (1 << self.useful.len()) - 1
+    }
+
+    /// Each valve still in `closed` that can be reached and opened with
+    /// time to spare, along with the minutes left once it's open.
+    fn choices(&self, at: usize, time_left: u32, closed: u64) -> Vec<(usize, u32)> {
+        (0..self.useful.len())
+            .filter(|valve| closed & (1 << valve) != 0)
+            .filter_map(|valve| {
+                let cost = self.distances[at][valve].saturating_add(1);
+                let remaining = time_left.checked_sub(cost)?;
+                (remaining > 0).then_some((valve, remaining))
+            })
+            .collect()
+    }
+
+    /// The most pressure each set of opened valves can release in
+    /// `minutes`, for every set one agent could open in that time.
+    fn best_by_set(&self, minutes: u32) -> HashMap<u64, u32> {
+        fn explore(
+            volcano: &Volcano,
+            at: usize,
+            time_left: u32,
+            closed: u64,
+            released: u32,
+            best: &mut HashMap<u64, u32>,
+        ) {
+            let entry = best.entry(volcano.all() & !closed).or_insert(0);
+            *entry = (*entry).max(released);
+            for (valve, remaining) in volcano.choices(at, time_left, closed) {
+                let released = released + volcano.flow(valve) * remaining;
+                explore(
+                    volcano,
+                    valve,
+                    remaining,
+                    closed & !(1 << valve),
+                    released,
+                    best,
+                );
+            }
+        }
+
+        let mut best = HashMap::new();
+        explore(self, self.start_idx(), minutes, self.all(), 0, &mut best);
+        best
+    }
+}
+
+/// Memoized search for the most pressure one agent can release.
+struct Planner<'a> {
+    volcano: &'a Volcano,
+    memo: HashMap<(usize, u32, u64), u32>,
+}
+
+impl<'a> Planner<'a> {
+    fn new(volcano: &'a Volcano) -> Self {
+        Self {
+            volcano,
+            memo: HashMap::new(),
+        }
+    }
+
+    /// The most pressure that can still be released standing at `at` with
+    /// `time_left` minutes, only opening valves in `closed`.
+    fn best(&mut self, at: usize, time_left: u32, closed: u64) -> u32 {
+        let key = (at, time_left, closed);
+        if let Some(best) = self.memo.get(&key) {
+            return *best;
+        }
+        let best = self
+            .volcano
+            .choices(at, time_left, closed)
+            .into_iter()
+            .map(|(valve, remaining)| {
+                self.volcano.flow(valve) * remaining
+                    + self.best(valve, remaining, closed & !(1 << valve))
+            })
+            .max()
+            .unwrap_or(0);
+        self.memo.insert(key, best);
+        best
+    }
+
+    /// The valves to open from the start to release the most pressure,
+    /// only choosing from `closed`.
+    fn route(&mut self, minutes: u32, closed: u64) -> Vec<Opening> {
+        let mut route = vec![];
+        let (mut at, mut time_left, mut closed) = (self.volcano.start_idx(), minutes, closed);
+        let mut target = self.best(at, time_left, closed);
+        while target > 0 {
+            let Some((valve, remaining)) = self
+                .volcano
+                .choices(at, time_left, closed)
+                .into_iter()
+                .find(|(valve, remaining)| {
+                    let gain = self.volcano.flow(*valve) * remaining;
+                    gain + self.best(*valve, *remaining, closed & !(1 << valve)) == target
+                })
+            else {
+                break;
+            };
+            target -= self.volcano.flow(valve) * remaining;
+            route.push(Opening {
+                valve,
+                minute: minutes - remaining,
+            });
+            (at, time_left, closed) = (valve, remaining, closed & !(1 << valve));
+        }
+        route
+    }
+}
+
+/// The best pair of disjoint sets for two agents working at once, and the
+/// pressure they release between them.
+fn best_pair(best: &HashMap<u64, u32>) -> (u32, u64, u64) {
+    let mut sets: Vec<(u64, u32)> = best.iter().map(|(set, score)| (*set, *score)).collect();
+    sets.sort_unstable_by_key(|(_, score)| std::cmp::Reverse(*score));
+    let mut found = (0, 0, 0);
+    for (idx, (mine, my_score)) in sets.iter().enumerate() {
+        for (theirs, their_score) in &sets[idx..] {
+            if my_score + their_score <= found.0 {
+                break;
+            }
+            // Anything later scores less, so the first disjoint set is the
+            // best partner for this one
+            if mine & theirs == 0 {
+                found = (my_score + their_score, *mine, *theirs);
+                break;
+            }
+        }
+    }
+    found
+}
+
+/// Who does the work, and how to describe what they're doing.
+const AGENTS: [(&str, &str, &str); 2] = [
+    ("You", "move to", "open"),
+    ("The elephant", "moves to", "opens"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Move(NodeIndex),
+    Open(NodeIndex),
+}
+
+/// What happens each minute, in the puzzle's words, with one route for each
+/// agent.
+fn schedule(volcano: &Volcano, minutes: u32, routes: &[Vec<Opening>]) -> String {
+    let actions: Vec<Vec<Action>> = routes
+        .iter()
+        .map(|route| {
+            let mut actions = vec![];
+            let mut at = volcano.start;
+            for opening in route {
+                let valve = volcano.useful[opening.valve];
+                let (_, path) =
+                    astar(&volcano.graph, at, |node| node == valve, |_| 1, |_| 0).unwrap();
+                actions.extend(path[1..].iter().map(|node| Action::Move(*node)));
+                actions.push(Action::Open(valve));
+                at = valve;
+            }
+            actions
+        })
+        .collect();
+
+    let mut out = String::new();
+    for minute in 1..=minutes {
+        out.push_str(&format!("== Minute {} ==\n", minute));
+        let mut open: Vec<(&str, u32)> = routes
+            .iter()
+            .flatten()
+            .filter(|opening| opening.minute < minute)
+            .map(|opening| {
+                let valve = &volcano.graph[volcano.useful[opening.valve]];
+                (valve.name.as_str(), valve.flow)
+            })
+            .collect();
+        open.sort_unstable();
+        let released: u32 = open.iter().map(|(_, flow)| flow).sum();
+        let names: Vec<&str> = open.iter().map(|(name, _)| *name).collect();
+        out.push_str(&match names.as_slice() {
+            [] => "No valves are open.\n".to_string(),
+            [name] => format!("Valve {} is open, releasing {} pressure.\n", name, released),
+            [first @ .., last] => format!(
+                "Valves {}{} and {} are open, releasing {} pressure.\n",
+                first.join(", "),
+                if first.len() > 1 { "," } else { "" },
+                last,
+                released
+            ),
+        });
+        for ((agent, moves, opens), actions) in AGENTS.iter().zip(&actions) {
+            match actions.get(minute as usize - 1) {
+                Some(Action::Move(node)) => {
+                    let name = volcano.name(*node);
+                    out.push_str(&format!("{} {} valve {}.\n", agent, moves, name));
+                }
+                Some(Action::Open(node)) => {
+                    let name = volcano.name(*node);
+                    out.push_str(&format!("{} {} valve {}.\n", agent, opens, name));
+                }
+                None => {}
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// `cargo run --bin 16 -- --schedule` prints what to do every minute.
+struct Options {
+    schedule: bool,
+}
+
+impl Options {
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            schedule: args.contains("--schedule"),
+        })
+    }
+}
+
+aoc_solutions::day_options!(Options);
+
+pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<u32>, anyhow::Error> {
+    let volcano = Volcano::new(input)?;
+    let mut planner = Planner::new(&volcano);
+    let best = planner.best(volcano.start_idx(), 30, volcano.all());
+    if Options::get()?.schedule {
+        let route = planner.route(30, volcano.all());
+        print!("{}", schedule(&volcano, 30, &[route]));
+    }
+    Ok(Some(best))
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<u32>, anyhow::Error> {
+    let volcano = Volcano::new(input)?;
+    let (best, mine, theirs) = best_pair(&volcano.best_by_set(26));
+    if Options::get()?.schedule {
+        let mut planner = Planner::new(&volcano);
+        let routes = [planner.route(26, mine), planner.route(26, theirs)];
+        print!("{}", schedule(&volcano, 26, &routes));
+    }
+    Ok(Some(best))
+}
+
+#[cfg(test)]
+mod tests_day_16 {
+    use super::*;
+
+    #[test]
+    fn test_part_one() -> anyhow::Result<()> {
+        let expected = Some(1651);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(expected.is_none() || !input.is_empty(), "example 1 empty!");
+        let result = part_one(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two() -> anyhow::Result<()> {
+        let expected = Some(1707);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
+        let result = part_two(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let volcano = Volcano::new(input)?;
+        let names: Vec<&str> = volcano.useful.iter().map(|n| volcano.name(*n)).collect();
+        assert_eq!(names, vec!["BB", "CC", "DD", "EE", "HH", "JJ"]);
+        // From the start, and from HH to JJ by way of AA
+        assert_eq!(volcano.distances[6], vec![1, 2, 1, 2, 5, 2]);
+        assert_eq!(volcano.distances[4][5], 7);
+
+        // The memoized search agrees with trying every route
+        let exhaustive = volcano.best_by_set(30).into_values().max();
+        let mut planner = Planner::new(&volcano);
+        assert_eq!(Some(planner.best(6, 30, volcano.all())), exhaustive);
+
+        assert!(Volcano::new(&input.replace("valves DD, II, BB", "valves DD, ZZ")).is_err());
+        assert!(Volcano::new(&input.replace("Valve AA", "Valve AB")).is_err());
+        Ok(())
+    }
+
+    /// The pressure the schedule says is released each minute.
+    fn released(schedule: &str) -> u32 {
+        schedule
+            .lines()
+            .filter_map(|line| line.split("releasing ").nth(1))
+            .map(|rest| rest.trim_end_matches(" pressure.").parse::<u32>().unwrap())
+            .sum()
+    }
+
+    #[test]
+    fn test_schedule() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let volcano = Volcano::new(input)?;
+        let mut planner = Planner::new(&volcano);
+
+        let route = planner.route(30, volcano.all());
+        let text = schedule(&volcano, 30, &[route]);
+        assert!(text.starts_with(
+            "== Minute 1 ==\nNo valves are open.\nYou move to valve DD.\n\n\
+             == Minute 2 ==\nNo valves are open.\nYou open valve DD.\n\n\
+             == Minute 3 ==\nValve DD is open, releasing 20 pressure.\nYou move to valve AA.\n"
+        ));
+        assert!(text.contains(
+            "== Minute 30 ==\nValves BB, CC, DD, EE, HH, and JJ are open, releasing 81 pressure.\n"
+        ));
+        assert_eq!(released(&text), 1651);
+
+        let (best, mine, theirs) = best_pair(&volcano.best_by_set(26));
+        assert_eq!(mine & theirs, 0);
+        let routes = [planner.route(26, mine), planner.route(26, theirs)];
+        let text = schedule(&volcano, 26, &routes);
+        assert!(text.contains("The elephant"));
+        assert_eq!(released(&text), best);
+        Ok(())
+    }
+}
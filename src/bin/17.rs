@@ -0,0 +1,373 @@
+advent_of_code::solution!(17);
+
+use advent_of_code::template::RunType;
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+
+const WIDTH: usize = 7;
+
+/// Up to four rows of a rock, bottom row first, along with how many of them
+/// it uses. Each row is a bitmask of the chamber's columns with the leftmost
+/// as the highest bit, and unused rows are left empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rock {
+    rows: [u8; 4],
+    height: usize,
+}
+
+/// The rocks in the order they fall, already placed two columns in from the
+/// left wall.
+const ROCKS: [Rock; 5] = [
+    Rock {
+        rows: [0b0011110, 0, 0, 0],
+        height: 1,
+    },
+    Rock {
+        rows: [0b0001000, 0b0011100, 0b0001000, 0],
+        height: 3,
+    },
+    Rock {
+        rows: [0b0011100, 0b0000100, 0b0000100, 0],
+        height: 3,
+    },
+    Rock {
+        rows: [0b0010000, 0b0010000, 0b0010000, 0b0010000],
+        height: 4,
+    },
+    Rock {
+        rows: [0b0011000, 0b0011000, 0, 0],
+        height: 2,
+    },
+];
+
+const LEFT_WALL: u8 = 1 << (WIDTH - 1);
+const RIGHT_WALL: u8 = 1;
+
+impl Rock {
+    fn rows(&self) -> &[u8] {
+        &self.rows[..self.height]
+    }
+
+    /// Where a jet pushes the rock, ignoring anything but the walls.
+    fn pushed(&self, jet: Jet) -> Rock {
+        let mut pushed = *self;
+        match jet {
+            Jet::Left if self.rows.iter().all(|row| row & LEFT_WALL == 0) => {
+                pushed.rows.iter_mut().for_each(|row| *row <<= 1);
+            }
+            Jet::Right if self.rows.iter().all(|row| row & RIGHT_WALL == 0) => {
+                pushed.rows.iter_mut().for_each(|row| *row >>= 1);
+            }
+            _ => {}
+        }
+        pushed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Jet {
+    Left,
+    Right,
+}
+
+fn parse(input: &str) -> Result<Vec<Jet>> {
+    let jets = input
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '<' => Ok(Jet::Left),
+            '>' => Ok(Jet::Right),
+            other => Err(anyhow!("Unexpected jet {:?}", other)),
+        })
+        .collect::<Result<Vec<Jet>>>()?;
+    if jets.is_empty() {
+        return Err(anyhow!("No jets in the input"));
+    }
+    Ok(jets)
+}
+
+/// Which rock and jet come next, and how far down from the top each column
+/// is filled to. Everything that happens from here on only depends on this.
+type State = (usize, usize, [usize; WIDTH]);
+
+struct Chamber<'a> {
+    jets: &'a [Jet],
+    /// Every settled row from the floor up, as bitmasks like the rocks
+    rows: Vec<u8>,
+    /// How high each column is filled, from the left
+    tops: [usize; WIDTH],
+    rocks: usize,
+    jet: usize,
+}
+
+impl<'a> Chamber<'a> {
+    fn new(jets: &'a [Jet]) -> Self {
+        Self {
+            jets,
+            rows: vec![],
+            tops: [0; WIDTH],
+            rocks: 0,
+            jet: 0,
+        }
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn collides(&self, rock: &Rock, bottom: usize) -> bool {
+        rock.rows()
+            .iter()
+            .enumerate()
+            .any(|(idx, row)| self.rows.get(bottom + idx).unwrap_or(&0) & row != 0)
+    }
+
+    /// Lets the next rock fall until it comes to rest.
+    fn drop_rock(&mut self) {
+        let mut rock = ROCKS[self.rocks % ROCKS.len()];
+        let mut bottom = self.height() + 3;
+        loop {
+            let jet = self.jets[self.jet];
+            self.jet = (self.jet + 1) % self.jets.len();
+            let pushed = rock.pushed(jet);
+            if !self.collides(&pushed, bottom) {
+                rock = pushed;
+            }
+            if bottom == 0 || self.collides(&rock, bottom - 1) {
+                break;
+            }
+            bottom -= 1;
+        }
+
+        for (idx, row) in rock.rows().iter().enumerate() {
+            let height = bottom + idx;
+            if height == self.rows.len() {
+                self.rows.push(0);
+            }
+            self.rows[height] |= row;
+            for (column, top) in self.tops.iter_mut().enumerate() {
+                if row & (LEFT_WALL >> column) != 0 {
+                    *top = (*top).max(height + 1);
+                }
+            }
+        }
+        self.rocks += 1;
+    }
+
+    /// How many rows down from the top each column's highest rock is, or
+    /// the height for an empty column.
+    fn profile(&self) -> [usize; WIDTH] {
+        self.tops.map(|top| self.height() - top)
+    }
+
+    fn state(&self) -> State {
+        (self.rocks % ROCKS.len(), self.jet, self.profile())
+    }
+
+    /// The top `rows` rows of the tower drawn like the puzzle does, with
+    /// the floor if it's in view.
+    fn render(&self, rows: usize) -> String {
+        let low = self.height().saturating_sub(rows);
+        let mut out = String::new();
+        for row in self.rows[low..].iter().rev() {
+            out.push('|');
+            for column in 0..WIDTH {
+                let bit = LEFT_WALL >> column;
+                out.push(if row & bit != 0 { '#' } else { '.' });
+            }
+            out.push_str("|\n");
+        }
+        if low == 0 {
+            out.push_str(&format!("+{}+\n", "-".repeat(WIDTH)));
+        }
+        out
+    }
+}
+
+/// A stretch of rocks that repeats forever once the tower gets going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    /// How many rocks fall before the first repeat starts
+    start: usize,
+    /// How many rocks fall in each repeat
+    length: usize,
+    /// How much taller the tower gets each repeat
+    growth: usize,
+}
+
+impl std::fmt::Display for Cycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cycle starts after {} rocks and repeats every {} rocks, adding {} rows",
+            self.start, self.length, self.growth
+        )
+    }
+}
+
+/// How tall the tower is after `rocks` rocks, given the height after each
+/// number of rocks up to the end of the first repeat of `cycle`.
+fn extrapolate(heights: &[usize], cycle: &Cycle, rocks: usize) -> usize {
+    let repeats = (rocks - cycle.start) / cycle.length;
+    let rest = (rocks - cycle.start) % cycle.length;
+    heights[cycle.start + rest] + repeats * cycle.growth
+}
+
+/// Drops rocks until `rocks` have fallen, or until the state repeats and
+/// the rest can be worked out from the cycle.
+fn tower_height(chamber: &mut Chamber, rocks: usize) -> (usize, Option<Cycle>) {
+    let mut heights = vec![chamber.height()];
+    let mut seen = HashMap::new();
+    seen.insert(chamber.state(), chamber.rocks);
+    while chamber.rocks < rocks {
+        chamber.drop_rock();
+        heights.push(chamber.height());
+        if let Some(start) = seen.insert(chamber.state(), chamber.rocks) {
+            let cycle = Cycle {
+                start,
+                length: chamber.rocks - start,
+                growth: chamber.height() - heights[start],
+            };
+            return (extrapolate(&heights, &cycle, rocks), Some(cycle));
+        }
+    }
+    (chamber.height(), None)
+}
+
+/// `cargo run --bin 17 -- --cycle --render 20` reports the cycle found and
+/// prints the top 20 rows of the tower once the simulation stops.
+struct Options {
+    cycle: bool,
+    render: Option<usize>,
+}
+
+impl Options {
+    fn parse(mut args: pico_args::Arguments) -> Result<Self> {
+        Ok(Self {
+            cycle: args.contains("--cycle"),
+            render: args.opt_value_from_str("--render")?,
+        })
+    }
+}
+
+aoc_solutions::day_options!(Options);
+
+fn solve(input: &str, rocks: usize, options: &Options) -> Result<Option<usize>> {
+    let jets = parse(input).context("Failed to parse input")?;
+    let mut chamber = Chamber::new(&jets);
+    let (height, cycle) = tower_height(&mut chamber, rocks);
+    if options.cycle {
+        match cycle {
+            Some(cycle) => println!("{}", cycle),
+            None => println!("no cycle within {} rocks", rocks),
+        }
+    }
+    if let Some(rows) = options.render {
+        println!("after {} rocks:\n{}", chamber.rocks, chamber.render(rows));
+    }
+    Ok(Some(height))
+}
+
+pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    solve(input, 2022, Options::get()?)
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    solve(input, 1_000_000_000_000, Options::get()?)
+}
+
+#[cfg(test)]
+mod tests_day_17 {
+    use super::*;
+
+    #[test]
+    fn test_part_one() -> anyhow::Result<()> {
+        let expected = Some(3068);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(expected.is_none() || !input.is_empty(), "example 1 empty!");
+        let result = part_one(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two() -> anyhow::Result<()> {
+        let expected = Some(1514285714288);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
+        let result = part_two(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cycle_matches_simulation() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let jets = parse(input)?;
+        let mut chamber = Chamber::new(&jets);
+        let (_, cycle) = tower_height(&mut chamber, 1_000_000);
+        let cycle = cycle.unwrap();
+        assert_eq!(cycle.length % ROCKS.len(), 0);
+
+        let mut simulated = Chamber::new(&jets);
+        let mut heights = vec![0];
+        for rocks in 1..=5000 {
+            simulated.drop_rock();
+            heights.push(simulated.height());
+            if rocks % 500 != 0 {
+                continue;
+            }
+            // The tops kept as rocks settle agree with scanning the rows
+            for (column, depth) in simulated.profile().into_iter().enumerate() {
+                let bit = LEFT_WALL >> column;
+                let scanned = simulated.rows.iter().rev().position(|row| row & bit != 0);
+                assert_eq!(depth, scanned.unwrap_or(simulated.height()));
+            }
+        }
+        for rocks in [cycle.start, 2022, 3333, 5000] {
+            assert_eq!(extrapolate(&heights, &cycle, rocks), heights[rocks]);
+            let mut fresh = Chamber::new(&jets);
+            assert_eq!(tower_height(&mut fresh, rocks).0, heights[rocks]);
+        }
+
+        assert!(parse("<<>x").is_err());
+        assert!(parse("\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_render() -> anyhow::Result<()> {
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        let jets = parse(input)?;
+        let mut chamber = Chamber::new(&jets);
+        for _ in 0..10 {
+            chamber.drop_rock();
+        }
+        assert_eq!(
+            chamber.render(100),
+            "|....#..|
+|....#..|
+|....##.|
+|##..##.|
+|######.|
+|.###...|
+|..#....|
+|.####..|
+|....##.|
+|....##.|
+|....#..|
+|..#.#..|
+|..#.#..|
+|#####..|
+|..###..|
+|...#...|
+|..####.|
++-------+
+"
+        );
+        assert_eq!(chamber.render(2), "|....#..|\n|....#..|\n");
+        assert_eq!(chamber.profile(), [3, 3, 4, 4, 0, 2, 17]);
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+advent_of_code::solution!(18);
+
+use advent_of_code::template::RunType;
+
+use anyhow::{Context, Result};
+use aoc_lib::parse::preamble::*;
+use aoc_solutions::space::{UnboundLocation3, Voxels};
+
+fn parse(input: &str) -> Result<Voxels> {
+    let cubes: Vec<UnboundLocation3> =
+        parse_input(LineSplitter, ParseFromStr, input).context("failed to parse input")?;
+    Ok(cubes.into_iter().collect())
+}
+
+pub fn part_one(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    Ok(Some(parse(input)?.surface_area()))
+}
+
+pub fn part_two(input: &str, _run_type: RunType) -> Result<Option<usize>, anyhow::Error> {
+    Ok(Some(parse(input)?.exterior_surface_area()))
+}
+
+#[cfg(test)]
+mod tests_day_18 {
+    use super::*;
+    use aoc_solutions::space::Direction3;
+
+    #[test]
+    fn test_part_one() -> anyhow::Result<()> {
+        let expected = Some(64);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 1);
+        assert!(expected.is_none() || !input.is_empty(), "example 1 empty!");
+        let result = part_one(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two() -> anyhow::Result<()> {
+        let expected = Some(58);
+        let input = &advent_of_code::template::read_file_part("examples", DAY, 2);
+        assert!(expected.is_none() || !input.is_empty(), "example 2 empty!");
+        let result = part_two(input, RunType::Example)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_locations() -> anyhow::Result<()> {
+        let origin = UnboundLocation3(0, 0, 0);
+        assert_eq!(
+            origin.go_direction(&Direction3::Up, 3),
+            UnboundLocation3(0, 0, 3)
+        );
+        assert_eq!(
+            origin.go_direction(&Direction3::West, 2),
+            UnboundLocation3(-2, 0, 0)
+        );
+        for direction in Direction3::all() {
+            let moved = origin.go_direction(direction, 1);
+            assert!(moved.neighbors().any(|back| back == origin));
+        }
+        assert_eq!(origin.neighbors().count(), 6);
+
+        assert_eq!(
+            " 1, -2,3".parse::<UnboundLocation3>()?,
+            UnboundLocation3(1, -2, 3)
+        );
+        assert!("1,2".parse::<UnboundLocation3>().is_err());
+        assert!("1,2,3,4".parse::<UnboundLocation3>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hollow_cube() {
+        // A 3x3x3 shell has the same outside as a solid cube but six more
+        // faces facing the hole in the middle
+        let shell: Voxels = (0..27)
+            .map(|idx| UnboundLocation3(idx % 3, idx / 3 % 3, idx / 9))
+            .filter(|location| *location != UnboundLocation3(1, 1, 1))
+            .collect();
+        assert_eq!(shell.surface_area(), 60);
+        assert_eq!(shell.exterior_surface_area(), 54);
+        assert_eq!(
+            shell.bounds(),
+            Some((UnboundLocation3(0, 0, 0), UnboundLocation3(2, 2, 2)))
+        );
+
+        // Knocking out the middle of the top face lets the outside in
+        let opened: Voxels = (0..27)
+            .map(|idx| UnboundLocation3(idx % 3, idx / 3 % 3, idx / 9))
+            .filter(|location| location.0 != 1 || location.1 != 1 || location.2 == 0)
+            .collect();
+        assert_eq!(opened.surface_area(), opened.exterior_surface_area());
+
+        let empty = Voxels::default();
+        assert_eq!(empty.bounds(), None);
+        assert_eq!(empty.exterior_surface_area(), 0);
+    }
+}
@@ -0,0 +1,5 @@
+//! Pieces shared between the days' binaries.
+
+pub mod options;
+pub mod random;
+pub mod space;
@@ -0,0 +1,31 @@
+//! Extra command line flags for a day, on top of the template's own.
+//!
+//! The template's `main` only hands each part the input, so a day reads its
+//! flags itself with `day_options!`. That happens once per run, and never in
+//! test builds: there the process arguments belong to libtest, and flags
+//! like `--exact` would otherwise be taken as the day's.
+
+/// Gives a day's `Options` a `get` that parses them the first time either
+/// part asks and returns the same ones after that. `Options` needs a
+/// `fn parse(pico_args::Arguments) -> anyhow::Result<Options>`, which tests
+/// get to call with no arguments at all.
+#[macro_export]
+macro_rules! day_options {
+    ($options:ty) => {
+        impl $options {
+            fn get() -> ::anyhow::Result<&'static $options> {
+                static OPTIONS: ::std::sync::OnceLock<$options> = ::std::sync::OnceLock::new();
+                if let Some(options) = OPTIONS.get() {
+                    return Ok(options);
+                }
+                let args = if cfg!(test) {
+                    ::pico_args::Arguments::from_vec(Vec::new())
+                } else {
+                    ::pico_args::Arguments::from_env()
+                };
+                let options = <$options>::parse(args)?;
+                Ok(OPTIONS.get_or_init(|| options))
+            }
+        }
+    };
+}
@@ -0,0 +1,23 @@
+//! Seeded numbers for the tests that check a day's answer against a slow,
+//! obvious one on made-up input. They only have to come out the same every
+//! run, so there's no need for a crate.
+
+/// Knuth's MMIX linear congruential generator.
+#[derive(Debug, Clone)]
+pub struct Lcg(u64);
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// A number in `0..n`, taken from the high bits as the low ones go round
+    /// in short cycles.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % n
+    }
+}
@@ -0,0 +1,173 @@
+//! Points and directions in three dimensions, along the lines of
+//! `aoc_lib::grid`'s `UnboundLocation` and `Direction`.
+
+use anyhow::{anyhow, Result};
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Direction3 {
+    Up,
+    Down,
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction3 {
+    pub fn all() -> &'static [Direction3; 6] {
+        &[
+            Direction3::Up,
+            Direction3::Down,
+            Direction3::North,
+            Direction3::South,
+            Direction3::East,
+            Direction3::West,
+        ]
+    }
+}
+
+/// A point with no bounds on any axis, as `x`, `y` and `z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct UnboundLocation3(pub i64, pub i64, pub i64);
+
+impl UnboundLocation3 {
+    /// Up and down move along `z`, north and south along `y`, and east
+    /// and west along `x`.
+    pub fn go_direction(&self, direction: &Direction3, n: i64) -> UnboundLocation3 {
+        let UnboundLocation3(x, y, z) = *self;
+        match direction {
+            Direction3::Up => UnboundLocation3(x, y, z + n),
+            Direction3::Down => UnboundLocation3(x, y, z - n),
+            Direction3::North => UnboundLocation3(x, y - n, z),
+            Direction3::South => UnboundLocation3(x, y + n, z),
+            Direction3::East => UnboundLocation3(x + n, y, z),
+            Direction3::West => UnboundLocation3(x - n, y, z),
+        }
+    }
+
+    /// The six points sharing a face with this one.
+    pub fn neighbors(&self) -> impl Iterator<Item = UnboundLocation3> + '_ {
+        Direction3::all()
+            .iter()
+            .map(move |direction| self.go_direction(direction, 1))
+    }
+
+    /// The lowest of each coordinate.
+    pub fn min_each(&self, other: &Self) -> Self {
+        UnboundLocation3(
+            self.0.min(other.0),
+            self.1.min(other.1),
+            self.2.min(other.2),
+        )
+    }
+
+    /// The highest of each coordinate.
+    pub fn max_each(&self, other: &Self) -> Self {
+        UnboundLocation3(
+            self.0.max(other.0),
+            self.1.max(other.1),
+            self.2.max(other.2),
+        )
+    }
+
+    /// Whether this is inside the box from `min` to `max`, both
+    /// included.
+    pub fn within(&self, min: &Self, max: &Self) -> bool {
+        (min.0..=max.0).contains(&self.0)
+            && (min.1..=max.1).contains(&self.1)
+            && (min.2..=max.2).contains(&self.2)
+    }
+}
+
+impl FromStr for UnboundLocation3 {
+    type Err = anyhow::Error;
+
+    /// Three comma separated coordinates, like `2,-1,5`.
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.trim().split(',').collect();
+        match parts.as_slice() {
+            [x, y, z] => Ok(UnboundLocation3(
+                x.trim().parse()?,
+                y.trim().parse()?,
+                z.trim().parse()?,
+            )),
+            _ => Err(anyhow!("Expected x,y,z but got {:?}", s)),
+        }
+    }
+}
+
+/// A set of unit cubes.
+#[derive(Debug, Clone, Default)]
+pub struct Voxels(HashSet<UnboundLocation3>);
+
+impl FromIterator<UnboundLocation3> for Voxels {
+    fn from_iter<I: IntoIterator<Item = UnboundLocation3>>(iter: I) -> Self {
+        Voxels(iter.into_iter().collect())
+    }
+}
+
+impl Voxels {
+    pub fn insert(&mut self, location: UnboundLocation3) -> bool {
+        self.0.insert(location)
+    }
+
+    pub fn contains(&self, location: &UnboundLocation3) -> bool {
+        self.0.contains(location)
+    }
+
+    /// The smallest box holding every cube, as its lowest and highest
+    /// corners.
+    pub fn bounds(&self) -> Option<(UnboundLocation3, UnboundLocation3)> {
+        let mut cubes = self.0.iter();
+        let first = *cubes.next()?;
+        Some(cubes.fold((first, first), |(min, max), cube| {
+            (min.min_each(cube), max.max_each(cube))
+        }))
+    }
+
+    /// How many faces don't touch another cube.
+    pub fn surface_area(&self) -> usize {
+        self.0
+            .iter()
+            .flat_map(|cube| cube.neighbors())
+            .filter(|neighbor| !self.contains(neighbor))
+            .count()
+    }
+
+    /// Everything the outside can reach without passing through a cube,
+    /// inside a box one bigger than the bounds all round. Filling from
+    /// a corner of that box means the water can flow round every side.
+    pub fn outside(&self) -> Voxels {
+        let Some((min, max)) = self.bounds() else {
+            return Voxels::default();
+        };
+        let (min, max) = (
+            UnboundLocation3(min.0 - 1, min.1 - 1, min.2 - 1),
+            UnboundLocation3(max.0 + 1, max.1 + 1, max.2 + 1),
+        );
+        let mut outside = Voxels::default();
+        let mut queue = VecDeque::from([min]);
+        outside.insert(min);
+        while let Some(location) = queue.pop_front() {
+            for next in location.neighbors() {
+                if next.within(&min, &max) && !self.contains(&next) && outside.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        outside
+    }
+
+    /// How many faces touch the outside, leaving out any facing a
+    /// pocket of air trapped inside.
+    pub fn exterior_surface_area(&self) -> usize {
+        let outside = self.outside();
+        self.0
+            .iter()
+            .flat_map(|cube| cube.neighbors())
+            .filter(|neighbor| outside.contains(neighbor))
+            .count()
+    }
+}